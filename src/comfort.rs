@@ -0,0 +1,130 @@
+use chrono::prelude::*;
+
+use crate::sampling::{Derive, Snapshot};
+use crate::sensors::measurement::{Measurement, Quantity, Unit};
+
+// Comfort metrics derived from the humidity and the temperature. The water
+// content of the air comes from the humidity sensor at its own temperature,
+// the metrics that depend on the air temperature use the main temperature
+// reading, which is not heated up by the processor.
+
+pub const COMFORT_ID_STR : &str = "comfort";
+pub const COMFORT_QUANTITIES : [Quantity; 5] = [
+    Quantity::DewPoint, Quantity::AbsHumidity, Quantity::HeatIndex, Quantity::Humidex, Quantity::MouldRisk,
+];
+
+// Magnus formula coefficients over water, -45 to 60 *C
+const MAGNUS_A : f64 = 17.62;
+const MAGNUS_B : f64 = 243.12;
+
+// Saturation vapour pressure in hPa
+pub fn saturation_pressure(t : f64) -> f64 {
+    6.112 * (MAGNUS_A * t / (MAGNUS_B + t)).exp()
+}
+
+// Dew point in *C from the temperature in *C and the relative humidity in %
+pub fn dew_point(t : f64, rh : f64) -> f64 {
+    let g = (rh.max(0.1) / 100.0).ln() + MAGNUS_A * t / (MAGNUS_B + t);
+    MAGNUS_B * g / (MAGNUS_A - g)
+}
+
+// Relative humidity in % of air at `t` with the given dew point
+pub fn relative_humidity(t : f64, dew_point : f64) -> f64 {
+    (100.0 * saturation_pressure(dew_point) / saturation_pressure(t)).min(100.0)
+}
+
+// Water vapour density in g/m3
+pub fn absolute_humidity(t : f64, dew_point : f64) -> f64 {
+    216.7 * saturation_pressure(dew_point) / (t + 273.15)
+}
+
+// NOAA heat index (Rothfusz regression with its adjustments) in *C
+pub fn heat_index(t : f64, rh : f64) -> f64 {
+    let f = t * 1.8 + 32.0;
+    let simple = 0.5 * (f + 61.0 + (f - 68.0) * 1.2 + rh * 0.094);
+    let hi = if (simple + f) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.049_015_23 * f + 10.143_331_27 * rh
+            - 0.224_755_41 * f * rh - 6.837_83e-3 * f * f
+            - 5.481_717e-2 * rh * rh + 1.228_74e-3 * f * f * rh
+            + 8.5282e-4 * f * rh * rh - 1.99e-6 * f * f * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&f) {
+            hi -= (13.0 - rh) / 4.0 * ((17.0 - (f - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&f) {
+            hi += (rh - 85.0) / 10.0 * (87.0 - f) / 5.0;
+        }
+        hi
+    };
+    (hi - 32.0) / 1.8
+}
+
+// Environment Canada humidex, a *C equivalent
+pub fn humidex(t : f64, dew_point : f64) -> f64 {
+    let e = 6.11 * (5417.7530 * (1.0 / 273.16 - 1.0 / (dew_point + 273.15))).exp();
+    t + 0.5555 * (e - 10.0)
+}
+
+// Risk of mould growth from the relative humidity : 0 low (below 70 %),
+// 1 moderate (70 to 80 %), 2 high (80 % and above)
+pub fn mould_risk(rh : f64) -> u8 {
+    if rh >= 80.0 {
+        2
+    } else if rh >= 70.0 {
+        1
+    } else {
+        0
+    }
+}
+
+pub struct Comfort {
+    // Timestamps of the humidity and temperature last used
+    last : Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl Comfort {
+    pub fn new() -> Comfort {
+        Comfort { last : None }
+    }
+}
+
+impl Default for Comfort {
+    fn default() -> Comfort {
+        Comfort::new()
+    }
+}
+
+impl Derive for Comfort {
+    fn id(&self) -> &str {
+        COMFORT_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &COMFORT_QUANTITIES
+    }
+
+    fn derive(&mut self, current : &Snapshot) -> Vec<Measurement> {
+        let (rh, t) = match (current.get("humidity"), current.get("temperature")) {
+            (Some(rh), Some(t)) => (rh, t),
+            _ => return Vec::new(),
+        };
+        if self.last == Some((rh.timestamp, t.timestamp)) {
+            return Vec::new();
+        }
+        self.last = Some((rh.timestamp, t.timestamp));
+
+        // Temperature the humidity was measured at
+        let rh_t = current.measurements().iter()
+            .find(|m| m.sensor == rh.sensor && m.quantity == Quantity::Temperature)
+            .unwrap_or(t);
+        let dp = dew_point(rh_t.value, rh.value);
+        let ambient_rh = relative_humidity(t.value, dp);
+        vec![
+            Measurement::new(self.id(), Quantity::DewPoint,    Unit::DegC,      dp),
+            Measurement::new(self.id(), Quantity::AbsHumidity, Unit::GramPerM3, absolute_humidity(t.value, dp)),
+            Measurement::new(self.id(), Quantity::HeatIndex,   Unit::DegC,      heat_index(t.value, ambient_rh)),
+            Measurement::new(self.id(), Quantity::Humidex,     Unit::DegC,      humidex(t.value, dp)),
+            Measurement::new(self.id(), Quantity::MouldRisk,   Unit::Code,      mould_risk(ambient_rh)),
+        ]
+    }
+}
@@ -0,0 +1,1071 @@
+use serde::Deserialize;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::hmi::matrix::Orientation;
+use crate::sensors::ads1115::*;
+use crate::sensors::airq::*;
+use crate::sensors::humidity::*;
+use crate::sensors::imu::*;
+use crate::sensors::light::{self, Gain};
+use crate::sensors::press_temp::*;
+use crate::sinks::{InfluxDbConf, MqttConf, MqttMode, SinkConf};
+use crate::storage::Retention;
+
+pub const DEFAULT_CONFIG_FILE : &str = "/home/pi/rpiapptmon.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Usage(String),
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid { field : String, msg : String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Usage(msg) => write!(f, "{}", msg),
+            ConfigError::Io(path, e) => write!(f, "Cannot read config file {} : {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Cannot parse config file {} : {}", path.display(), e),
+            ConfigError::Invalid { field, msg } => write!(f, "Invalid config value for `{}` : {}", field, msg),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+fn invalid<T, S : Into<String>>(field : &str, msg : S) -> Result<T, ConfigError> {
+    Err(ConfigError::Invalid { field : field.to_owned(), msg : msg.into() })
+}
+
+// Maps a numeric config value onto a register setting, listing the accepted
+// values when it matches none of them.
+fn lookup<T : Copy>(field : &str, val : f32, table : &[(f32, T)]) -> Result<T, ConfigError> {
+    match table.iter().find(|(v, _)| (v - val).abs() < 1e-3) {
+        Some((_, t)) => Ok(*t),
+        None => {
+            let allowed : Vec<String> = table.iter().map(|(v, _)| v.to_string()).collect();
+            invalid(field, format!("{} is not one of {}", val, allowed.join(", ")))
+        },
+    }
+}
+
+fn lookup_str<T : Copy>(field : &str, val : &str, table : &[(&str, T)]) -> Result<T, ConfigError> {
+    match table.iter().find(|(v, _)| v.eq_ignore_ascii_case(val)) {
+        Some((_, t)) => Ok(*t),
+        None => {
+            let allowed : Vec<String> = table.iter().map(|(v, _)| format!("\"{}\"", v)).collect();
+            invalid(field, format!("\"{}\" is not one of {}", val, allowed.join(", ")))
+        },
+    }
+}
+
+fn yes() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    general   : RawGeneral,
+    #[serde(default)]
+    telemetry : RawTelemetry,
+    #[serde(default)]
+    storage   : RawStorage,
+    #[serde(default)]
+    web       : RawWeb,
+    #[serde(default)]
+    matrix    : RawMatrix,
+    #[serde(default)]
+    sensors   : RawSensors,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawGeneral {
+    log_file         : PathBuf,
+    i2c_bus          : String,
+    sample_interval  : u64,
+    station_altitude : Option<f32>,
+    qnh              : Option<f32>,
+}
+
+impl Default for RawGeneral {
+    fn default() -> RawGeneral {
+        RawGeneral {
+            log_file         : PathBuf::from("/home/pi/log.txt"),
+            i2c_bus          : "/dev/i2c-1".to_owned(),
+            sample_interval  : 30,
+            station_altitude : None,
+            qnh              : None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawTelemetry {
+    outbox_dir : PathBuf,
+    outbox_max : usize,
+    retry_max  : u64,
+    sink       : Vec<RawSink>,
+}
+
+impl Default for RawTelemetry {
+    fn default() -> RawTelemetry {
+        RawTelemetry {
+            outbox_dir : PathBuf::from("/home/pi/rpiapptmon-outbox"),
+            outbox_max : 10000,
+            retry_max  : 600,
+            sink       : Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum RawSink {
+    Thingsboard {
+        #[serde(default = "RawSink::default_tb_host")]
+        host         : String,
+        access_token : String,
+    },
+    Mqtt {
+        host         : String,
+        #[serde(default = "RawSink::default_mqtt_mode")]
+        mode         : String,
+        access_token : Option<String>,
+        #[serde(default = "RawSink::default_name")]
+        topic        : String,
+        #[serde(default = "RawSink::default_name")]
+        client_id    : String,
+        username     : Option<String>,
+        password     : Option<String>,
+        #[serde(default = "RawSink::default_keep_alive")]
+        keep_alive   : u64,
+    },
+    Influxdb {
+        url          : String,
+        database     : String,
+        #[serde(default = "RawSink::default_name")]
+        measurement  : String,
+        token        : Option<String>,
+    },
+    Jsonl {
+        path         : Option<PathBuf>,
+    },
+}
+
+impl RawSink {
+    fn default_tb_host() -> String {
+        "http://localhost:8080".to_owned()
+    }
+
+    fn default_mqtt_mode() -> String {
+        "thingsboard".to_owned()
+    }
+
+    fn default_name() -> String {
+        "rpiapptmon".to_owned()
+    }
+
+    fn default_keep_alive() -> u64 {
+        60
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            RawSink::Thingsboard { .. } => "thingsboard",
+            RawSink::Mqtt { .. } => "mqtt",
+            RawSink::Influxdb { .. } => "influxdb",
+            RawSink::Jsonl { .. } => "jsonl",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawStorage {
+    enabled     : bool,
+    dir         : PathBuf,
+    raw_days    : u32,
+    minute_days : u32,
+    hour_days   : u32,
+    day_days    : u32,
+}
+
+impl Default for RawStorage {
+    fn default() -> RawStorage {
+        RawStorage {
+            enabled     : true,
+            dir         : PathBuf::from("/home/pi/rpiapptmon-data"),
+            raw_days    : 7,
+            minute_days : 30,
+            hour_days   : 365,
+            day_days    : 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawWeb {
+    enabled : bool,
+    listen  : String,
+}
+
+impl Default for RawWeb {
+    fn default() -> RawWeb {
+        RawWeb {
+            enabled : true,
+            listen  : "0.0.0.0:8000".to_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawMatrix {
+    enabled     : bool,
+    framebuffer : String,
+    rotation    : u16,
+}
+
+impl Default for RawMatrix {
+    fn default() -> RawMatrix {
+        RawMatrix {
+            enabled     : false,
+            framebuffer : "/dev/fb1".to_owned(),
+            rotation    : 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RawSensors {
+    bmp180   : Option<RawBmp180>,
+    lps25h   : Option<RawLps25h>,
+    hts221   : Option<RawHts221>,
+    tcs34725 : Option<RawTcs34725>,
+    mics5524 : Option<RawMics5524>,
+    ads1115  : Option<RawAds1115>,
+    lsm9ds1  : Option<RawLsm9ds1>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBmp180 {
+    #[serde(default = "yes")]
+    enabled  : bool,
+    #[serde(default)]
+    interval : u64,
+    #[serde(default = "RawBmp180::default_oss")]
+    oss      : u8,
+}
+
+impl RawBmp180 {
+    fn default_oss() -> u8 {
+        4
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLps25h {
+    #[serde(default = "yes")]
+    enabled         : bool,
+    #[serde(default)]
+    interval        : u64,
+    #[serde(default = "RawLps25h::default_odr")]
+    odr             : String,
+    #[serde(default = "RawLps25h::default_temperature_avg")]
+    temperature_avg : u16,
+    #[serde(default = "RawLps25h::default_pressure_avg")]
+    pressure_avg    : u16,
+    #[serde(default)]
+    fifo_mean       : u8,
+}
+
+impl RawLps25h {
+    fn default_odr() -> String {
+        "one-shot".to_owned()
+    }
+
+    fn default_temperature_avg() -> u16 {
+        16
+    }
+
+    fn default_pressure_avg() -> u16 {
+        32
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHts221 {
+    #[serde(default = "yes")]
+    enabled         : bool,
+    #[serde(default)]
+    interval        : u64,
+    #[serde(default = "RawHts221::default_odr")]
+    odr             : String,
+    #[serde(default = "RawHts221::default_avg")]
+    humidity_avg    : u16,
+    #[serde(default = "RawHts221::default_avg")]
+    temperature_avg : u16,
+}
+
+impl RawHts221 {
+    fn default_odr() -> String {
+        "one-shot".to_owned()
+    }
+
+    fn default_avg() -> u16 {
+        4
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTcs34725 {
+    #[serde(default = "yes")]
+    enabled        : bool,
+    #[serde(default)]
+    interval       : u64,
+    #[serde(default = "RawTcs34725::default_gain")]
+    gain           : u8,
+    #[serde(default = "RawTcs34725::default_integration_ms")]
+    integration_ms : f32,
+    #[serde(default = "RawTcs34725::default_mode")]
+    mode           : String,
+    #[serde(default)]
+    wait_ms        : f32,
+    #[serde(default)]
+    auto_range     : bool,
+    #[serde(default)]
+    switch_on      : u16,
+    #[serde(default)]
+    switch_off     : u16,
+    #[serde(default = "RawTcs34725::default_persistence")]
+    persistence    : u8,
+}
+
+impl RawTcs34725 {
+    fn default_gain() -> u8 {
+        16
+    }
+
+    fn default_integration_ms() -> f32 {
+        30.0
+    }
+
+    fn default_mode() -> String {
+        "one-shot".to_owned()
+    }
+
+    fn default_persistence() -> u8 {
+        5
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMics5524 {
+    #[serde(default = "yes")]
+    enabled   : bool,
+    #[serde(default)]
+    interval  : u64,
+    #[serde(default = "RawMics5524::default_channel")]
+    channel   : String,
+    #[serde(default = "RawMics5524::default_fsr")]
+    fsr       : f32,
+    #[serde(default = "RawMics5524::default_data_rate")]
+    data_rate : u16,
+    #[serde(default = "RawMics5524::default_divider")]
+    divider   : f32,
+}
+
+impl RawMics5524 {
+    fn default_channel() -> String {
+        "an0".to_owned()
+    }
+
+    fn default_fsr() -> f32 {
+        6.144
+    }
+
+    fn default_data_rate() -> u16 {
+        32
+    }
+
+    fn default_divider() -> f32 {
+        1.0
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAds1115 {
+    #[serde(default = "yes")]
+    enabled   : bool,
+    #[serde(default)]
+    interval  : u64,
+    #[serde(default = "RawMics5524::default_data_rate")]
+    data_rate : u16,
+    #[serde(default)]
+    input     : Vec<RawAdcInput>,
+    alert     : Option<RawAdcAlert>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAdcAlert {
+    channel     : String,
+    #[serde(default = "RawMics5524::default_fsr")]
+    fsr         : f32,
+    #[serde(default = "RawMics5524::default_divider")]
+    divider     : f32,
+    #[serde(default = "RawAdcAlert::default_mode")]
+    mode        : String,
+    low         : f32,
+    high        : f32,
+    #[serde(default = "RawAdcAlert::default_queue")]
+    queue       : u8,
+    #[serde(default = "yes")]
+    latch       : bool,
+    #[serde(default)]
+    active_high : bool,
+    gpio        : Option<u32>,
+}
+
+impl RawAdcAlert {
+    fn default_mode() -> String {
+        "traditional".to_owned()
+    }
+
+    fn default_queue() -> u8 {
+        1
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAdcInput {
+    channel : String,
+    #[serde(default = "RawMics5524::default_fsr")]
+    fsr     : f32,
+    #[serde(default = "RawMics5524::default_divider")]
+    divider : f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLsm9ds1 {
+    #[serde(default = "yes")]
+    enabled     : bool,
+    #[serde(default)]
+    interval    : u64,
+    #[serde(default = "RawLsm9ds1::default_odr")]
+    gyro_odr    : f32,
+    #[serde(default = "RawLsm9ds1::default_gyro_range")]
+    gyro_range  : u16,
+    #[serde(default = "RawLsm9ds1::default_odr")]
+    accel_odr   : f32,
+    #[serde(default = "RawLsm9ds1::default_accel_range")]
+    accel_range : u8,
+    #[serde(default = "RawLsm9ds1::default_mag_odr")]
+    mag_odr     : f32,
+    #[serde(default = "RawLsm9ds1::default_mag_range")]
+    mag_range   : u8,
+}
+
+impl RawLsm9ds1 {
+    fn default_odr() -> f32 {
+        119.0
+    }
+
+    fn default_gyro_range() -> u16 {
+        245
+    }
+
+    fn default_accel_range() -> u8 {
+        2
+    }
+
+    fn default_mag_odr() -> f32 {
+        10.0
+    }
+
+    fn default_mag_range() -> u8 {
+        4
+    }
+}
+
+pub struct General {
+    pub log_file        : PathBuf,
+    pub i2c_bus         : String,
+    pub sample_interval : Duration,
+    // Station altitude and QNH (in Pa) for the pressure sensors
+    pub pressure_ref    : PressureRef,
+}
+
+pub struct Sink {
+    // Type name, numbered when several sinks share a type : "mqtt", "mqtt_2"
+    pub name : String,
+    pub conf : SinkConf,
+}
+
+pub struct Telemetry {
+    pub outbox_dir : PathBuf,
+    pub outbox_max : usize,
+    pub retry_max  : Duration,
+    pub sinks      : Vec<Sink>,
+}
+
+pub struct Storage {
+    pub dir       : PathBuf,
+    pub retention : Retention,
+}
+
+pub struct Web {
+    pub listen : String,
+}
+
+pub struct MatrixConf {
+    pub framebuffer : String,
+    pub orientation : Orientation,
+}
+
+#[derive(Copy, Clone)]
+pub struct Hts221Conf {
+    pub havg : HumAvgCfg,
+    pub tavg : TempAvgCfg,
+    pub odr  : OutDataRate,
+}
+
+// A sensor whose section is missing or has `enabled = false` is `None`.
+// `intervals` lists the sensors read at their own pace, by sensor id.
+pub struct Config {
+    pub general   : General,
+    pub intervals : Vec<(&'static str, Duration)>,
+    pub telemetry : Telemetry,
+    pub storage   : Option<Storage>,
+    pub web       : Option<Web>,
+    pub matrix    : Option<MatrixConf>,
+    pub bmp180    : Option<Oss>,
+    pub lps25h    : Option<Lps25hConf>,
+    pub hts221    : Option<Hts221Conf>,
+    pub tcs34725  : Option<light::LightConf>,
+    pub mics5524  : Option<AdcInput>,
+    // Inputs of the ADC read as plain voltages, and its comparator
+    pub ads1115   : Option<AdcScanConf>,
+    pub lsm9ds1   : Option<ImuConf>,
+}
+
+impl Config {
+    // Config file given with `--config <path>`, or the default one
+    pub fn path_from_args<I : Iterator<Item = String>>(args : I) -> Result<PathBuf, ConfigError> {
+        let mut args = args.skip(1);
+        let mut path = PathBuf::from(DEFAULT_CONFIG_FILE);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => match args.next() {
+                    Some(p) => path = PathBuf::from(p),
+                    None => return Err(ConfigError::Usage(format!("Missing path after {}", arg))),
+                },
+                _ => return Err(ConfigError::Usage(format!("Unknown argument {}\nUsage : rpiapptmon [--config <file>]", arg))),
+            }
+        }
+        Ok(path)
+    }
+
+    // Time between two reads of a sensor
+    pub fn interval(&self, sensor : &str) -> Duration {
+        match self.intervals.iter().find(|(id, _)| *id == sensor) {
+            Some((_, interval)) => *interval,
+            None => self.general.sample_interval,
+        }
+    }
+
+    pub fn load<P : AsRef<Path>>(path : P) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let raw : RawConfig = toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        Config::from_raw(raw)
+    }
+
+    pub fn parse(text : &str) -> Result<Config, ConfigError> {
+        let raw : RawConfig = toml::from_str(text).map_err(|e| ConfigError::Parse(PathBuf::from("<string>"), e))?;
+        Config::from_raw(raw)
+    }
+
+    fn from_raw(raw : RawConfig) -> Result<Config, ConfigError> {
+        if raw.general.sample_interval == 0 {
+            return invalid("general.sample_interval", "must be at least 1 s");
+        }
+        if raw.general.i2c_bus.is_empty() {
+            return invalid("general.i2c_bus", "must not be empty");
+        }
+        if let Some(alt) = raw.general.station_altitude {
+            if !(-500.0..=9000.0).contains(&alt) {
+                return invalid("general.station_altitude", format!("{} m is not between -500 and 9000", alt));
+            }
+        }
+        if let Some(qnh) = raw.general.qnh {
+            if !(850.0..=1100.0).contains(&qnh) {
+                return invalid("general.qnh", format!("{} hPa is not between 850 and 1100", qnh));
+            }
+        }
+        if raw.telemetry.outbox_max == 0 {
+            return invalid("telemetry.outbox_max", "must be at least 1");
+        }
+        if raw.telemetry.retry_max < raw.general.sample_interval {
+            return invalid("telemetry.retry_max", "must not be shorter than general.sample_interval");
+        }
+
+        let mut sinks : Vec<Sink> = Vec::new();
+        for (i, rs) in raw.telemetry.sink.iter().enumerate() {
+            let same = sinks.iter().filter(|s| s.name.split('_').next() == Some(rs.type_name())).count();
+            let name = if same == 0 { rs.type_name().to_owned() } else { format!("{}_{}", rs.type_name(), same + 1) };
+            sinks.push(Sink { name, conf : sink(i, rs)? });
+        }
+
+        let days = |d : u32| if d == 0 { None } else { Some(Duration::from_secs(d as u64 * 86400)) };
+        let storage = raw.storage;
+        if storage.enabled && storage.dir.as_os_str().is_empty() {
+            return invalid("storage.dir", "must not be empty");
+        }
+
+        let web = raw.web;
+        if web.enabled && web.listen.parse::<SocketAddr>().is_err() {
+            return invalid("web.listen", format!("\"{}\" is not an <ip>:<port> address", web.listen));
+        }
+
+        let matrix = raw.matrix;
+        if matrix.enabled && matrix.framebuffer.is_empty() {
+            return invalid("matrix.framebuffer", "must not be empty");
+        }
+        let orientation = lookup("matrix.rotation", matrix.rotation as f32, &[
+            (0.0, Orientation::Cw0), (90.0, Orientation::Cw90), (180.0, Orientation::Cw180), (270.0, Orientation::Cw270),
+        ])?;
+
+        let sensors = raw.sensors;
+        let intervals = [
+            (BMP180_ID_STR,       sensors.bmp180.as_ref().map(|s| s.interval)),
+            (LPS25H_ID_STR,       sensors.lps25h.as_ref().map(|s| s.interval)),
+            (HUM_ID_STR,          sensors.hts221.as_ref().map(|s| s.interval)),
+            (light::LIGHT_ID_STR, sensors.tcs34725.as_ref().map(|s| s.interval)),
+            (AIRQ_ID_STR,         sensors.mics5524.as_ref().map(|s| s.interval)),
+            (ADC_ID_STR,          sensors.ads1115.as_ref().map(|s| s.interval)),
+            (IMU_ID_STR,          sensors.lsm9ds1.as_ref().map(|s| s.interval)),
+        ];
+        // 0 (the default) follows general.sample_interval
+        let intervals = intervals.iter()
+            .filter_map(|(id, i)| match i {
+                Some(i) if *i > 0 => Some((*id, Duration::from_secs(*i))),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Config {
+            general : General {
+                log_file        : raw.general.log_file,
+                i2c_bus         : raw.general.i2c_bus,
+                sample_interval : Duration::from_secs(raw.general.sample_interval),
+                pressure_ref    : PressureRef {
+                    station_altitude : raw.general.station_altitude,
+                    qnh              : raw.general.qnh.map(|hpa| hpa * 100.0),
+                },
+            },
+            intervals,
+            telemetry : Telemetry {
+                outbox_dir : raw.telemetry.outbox_dir,
+                outbox_max : raw.telemetry.outbox_max,
+                retry_max  : Duration::from_secs(raw.telemetry.retry_max),
+                sinks,
+            },
+            storage : if storage.enabled {
+                Some(Storage {
+                    dir : storage.dir,
+                    retention : Retention {
+                        raw    : days(storage.raw_days),
+                        minute : days(storage.minute_days),
+                        hour   : days(storage.hour_days),
+                        day    : days(storage.day_days),
+                    },
+                })
+            } else {
+                None
+            },
+            web : if web.enabled { Some(Web { listen : web.listen }) } else { None },
+            matrix : if matrix.enabled {
+                Some(MatrixConf { framebuffer : matrix.framebuffer, orientation })
+            } else {
+                None
+            },
+            bmp180   : match sensors.bmp180   { Some(ref s) if s.enabled => Some(bmp180(s)?),   _ => None },
+            lps25h   : match sensors.lps25h   { Some(ref s) if s.enabled => Some(lps25h(s)?),   _ => None },
+            hts221   : match sensors.hts221   { Some(ref s) if s.enabled => Some(hts221(s)?),   _ => None },
+            tcs34725 : match sensors.tcs34725 { Some(ref s) if s.enabled => Some(tcs34725(s)?), _ => None },
+            mics5524 : match sensors.mics5524 { Some(ref s) if s.enabled => Some(mics5524(s)?), _ => None },
+            ads1115  : match sensors.ads1115  { Some(ref s) if s.enabled => Some(ads1115(s)?),  _ => None },
+            lsm9ds1  : match sensors.lsm9ds1  { Some(ref s) if s.enabled => Some(lsm9ds1(s)?),  _ => None },
+        })
+    }
+}
+
+fn is_http(url : &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn sink(i : usize, s : &RawSink) -> Result<SinkConf, ConfigError> {
+    let field = |name : &str| format!("telemetry.sink[{}].{}", i, name);
+    match s {
+        RawSink::Thingsboard { host, access_token } => {
+            if !is_http(host) {
+                return invalid(&field("host"), format!("\"{}\" is not an http(s) URL", host));
+            }
+            if access_token.trim().is_empty() {
+                return invalid(&field("access_token"), "must not be empty");
+            }
+            Ok(SinkConf::ThingsBoard {
+                url : format!("{}/api/v1/{}/telemetry", host.trim_end_matches('/'), access_token),
+            })
+        },
+        RawSink::Mqtt { host, mode, access_token, topic, client_id, username, password, keep_alive } => {
+            if host.is_empty() {
+                return invalid(&field("host"), "must not be empty");
+            }
+            let mode = lookup_str(&field("mode"), mode, &[("thingsboard", true), ("plain", false)])?;
+            let (mode, username) = if mode {
+                match access_token {
+                    Some(token) if !token.trim().is_empty() => (MqttMode::ThingsBoard, Some(token.clone())),
+                    _ => return invalid(&field("access_token"), "is required in \"thingsboard\" mode"),
+                }
+            } else {
+                if topic.is_empty() || topic.contains(&['+', '#'][..]) {
+                    return invalid(&field("topic"), format!("\"{}\" is not a topic name", topic));
+                }
+                (MqttMode::Plain { topic : topic.trim_end_matches('/').to_owned() }, username.clone())
+            };
+            Ok(SinkConf::Mqtt(MqttConf {
+                host : if host.contains(':') { host.clone() } else { format!("{}:1883", host) },
+                client_id : client_id.clone(),
+                username,
+                password : password.clone(),
+                keep_alive : Duration::from_secs(*keep_alive),
+                mode,
+            }))
+        },
+        RawSink::Influxdb { url, database, measurement, token } => {
+            if !is_http(url) {
+                return invalid(&field("url"), format!("\"{}\" is not an http(s) URL", url));
+            }
+            if database.is_empty() || !database.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return invalid(&field("database"), "must be made of letters, digits, '_' and '-'");
+            }
+            if measurement.is_empty() {
+                return invalid(&field("measurement"), "must not be empty");
+            }
+            Ok(SinkConf::InfluxDb(InfluxDbConf {
+                url : url.clone(),
+                database : database.clone(),
+                measurement : measurement.clone(),
+                token : token.clone(),
+            }))
+        },
+        RawSink::Jsonl { path } => Ok(SinkConf::JsonLines {
+            path : match path {
+                Some(p) if p.as_os_str() != "-" => Some(p.clone()),
+                _ => None,
+            },
+        }),
+    }
+}
+
+fn bmp180(s : &RawBmp180) -> Result<Oss, ConfigError> {
+    lookup("sensors.bmp180.oss", s.oss as f32, &[
+        (1.0, Oss::Oss1), (2.0, Oss::Oss2), (4.0, Oss::Oss4), (8.0, Oss::Oss8),
+    ])
+}
+
+fn lps25h(s : &RawLps25h) -> Result<Lps25hConf, ConfigError> {
+    Ok(Lps25hConf {
+        odr : lookup_str("sensors.lps25h.odr", &s.odr, &[
+            ("one-shot", Lps25hOdr::OneShot),
+            ("1Hz",      Lps25hOdr::DataRate1Hz),
+            ("7Hz",      Lps25hOdr::DataRate7Hz),
+            ("12.5Hz",   Lps25hOdr::DataRate12p5Hz),
+            ("25Hz",     Lps25hOdr::DataRate25Hz),
+        ])?,
+        tavg : lookup("sensors.lps25h.temperature_avg", s.temperature_avg as f32, &[
+            (8.0,  Lps25hTempAvg::AvgSmplx8),
+            (16.0, Lps25hTempAvg::AvgSmplx16),
+            (32.0, Lps25hTempAvg::AvgSmplx32),
+            (64.0, Lps25hTempAvg::AvgSmplx64),
+        ])?,
+        pavg : lookup("sensors.lps25h.pressure_avg", s.pressure_avg as f32, &[
+            (8.0,   Lps25hPressAvg::AvgSmplx8),
+            (32.0,  Lps25hPressAvg::AvgSmplx32),
+            (128.0, Lps25hPressAvg::AvgSmplx128),
+            (512.0, Lps25hPressAvg::AvgSmplx512),
+        ])?,
+        fifo_mean : lookup("sensors.lps25h.fifo_mean", s.fifo_mean as f32, &[
+            (0.0,  None),
+            (2.0,  Some(FifoMeanSmpl::Mean2)),
+            (4.0,  Some(FifoMeanSmpl::Mean4)),
+            (8.0,  Some(FifoMeanSmpl::Mean8)),
+            (16.0, Some(FifoMeanSmpl::Mean16)),
+            (32.0, Some(FifoMeanSmpl::Mean32)),
+        ])?,
+    })
+}
+
+fn hts221(s : &RawHts221) -> Result<Hts221Conf, ConfigError> {
+    Ok(Hts221Conf {
+        havg : lookup("sensors.hts221.humidity_avg", s.humidity_avg as f32, &[
+            (4.0,   HumAvgCfg::AvgSmplx4),
+            (8.0,   HumAvgCfg::AvgSmplx8),
+            (16.0,  HumAvgCfg::AvgSmplx16),
+            (32.0,  HumAvgCfg::AvgSmplx32),
+            (64.0,  HumAvgCfg::AvgSmplx64),
+            (128.0, HumAvgCfg::AvgSmplx128),
+            (256.0, HumAvgCfg::AvgSmplx256),
+            (512.0, HumAvgCfg::AvgSmplx512),
+        ])?,
+        tavg : lookup("sensors.hts221.temperature_avg", s.temperature_avg as f32, &[
+            (2.0,   TempAvgCfg::AvgSmplx2),
+            (4.0,   TempAvgCfg::AvgSmplx4),
+            (8.0,   TempAvgCfg::AvgSmplx8),
+            (16.0,  TempAvgCfg::AvgSmplx16),
+            (32.0,  TempAvgCfg::AvgSmplx32),
+            (64.0,  TempAvgCfg::AvgSmplx64),
+            (128.0, TempAvgCfg::AvgSmplx128),
+            (256.0, TempAvgCfg::AvgSmplx256),
+        ])?,
+        odr : lookup_str("sensors.hts221.odr", &s.odr, &[
+            ("one-shot", OutDataRate::SingleShot),
+            ("1Hz",      OutDataRate::DataRate1Hz),
+            ("7Hz",      OutDataRate::DataRate7Hz),
+            ("12.5Hz",   OutDataRate::DataRate12p5Hz),
+        ])?,
+    })
+}
+
+fn tcs34725(s : &RawTcs34725) -> Result<light::LightConf, ConfigError> {
+    if s.integration_ms < 2.4 || s.integration_ms > 614.4 {
+        return invalid("sensors.tcs34725.integration_ms", format!("{} is outside 2.4..614.4 ms", s.integration_ms));
+    }
+    // switch_on = 0 (the default) does not watch the lights
+    let watch = if s.switch_on > 0 {
+        if s.switch_off >= s.switch_on {
+            return invalid("sensors.tcs34725.switch_off", "must be below switch_on");
+        }
+        if s.auto_range {
+            return invalid("sensors.tcs34725.auto_range", "cannot be used with switch_on, the levels are counts at a fixed range");
+        }
+        Some(light::Watch {
+            off  : s.switch_off,
+            on   : s.switch_on,
+            pers : lookup("sensors.tcs34725.persistence", s.persistence as f32, &[
+                (0.0,  light::Persistence::Every),    (1.0,  light::Persistence::Cycles1),
+                (2.0,  light::Persistence::Cycles2),  (3.0,  light::Persistence::Cycles3),
+                (5.0,  light::Persistence::Cycles5),  (10.0, light::Persistence::Cycles10),
+                (15.0, light::Persistence::Cycles15), (20.0, light::Persistence::Cycles20),
+                (25.0, light::Persistence::Cycles25), (30.0, light::Persistence::Cycles30),
+                (35.0, light::Persistence::Cycles35), (40.0, light::Persistence::Cycles40),
+                (45.0, light::Persistence::Cycles45), (50.0, light::Persistence::Cycles50),
+                (55.0, light::Persistence::Cycles55), (60.0, light::Persistence::Cycles60),
+            ])?,
+        })
+    } else {
+        None
+    };
+    if s.wait_ms < 0.0 || s.wait_ms > 7372.8 {
+        return invalid("sensors.tcs34725.wait_ms", format!("{} is outside 0..7372.8 ms", s.wait_ms));
+    }
+    let mode = match lookup_str("sensors.tcs34725.mode", &s.mode, &[("one-shot", false), ("continuous", true)])? {
+        false if s.wait_ms > 0.0 => return invalid("sensors.tcs34725.wait_ms", "only applies to mode = \"continuous\""),
+        false => light::LightMode::OneShot,
+        true if s.wait_ms > 0.0 => light::LightMode::LowPower(light::WaitTime::from_ms(s.wait_ms)),
+        true => light::LightMode::Continuous,
+    };
+    let conf = light::LightConf::default()
+        .gain(lookup("sensors.tcs34725.gain", s.gain as f32, &[
+            (1.0, Gain::X1), (4.0, Gain::X4), (16.0, Gain::X16), (60.0, Gain::X60),
+        ])?)
+        .integration_ms(s.integration_ms)
+        .mode(mode)
+        .auto_range(s.auto_range);
+    Ok(match watch {
+        Some(w) => conf.watch(w),
+        None => conf,
+    })
+}
+
+fn adc_channel(field : &str, ch : &str) -> Result<Channel, ConfigError> {
+    lookup_str(field, ch, &[
+        ("an0",     Channel::ChRelAn0),
+        ("an1",     Channel::ChRelAn1),
+        ("an2",     Channel::ChRelAn2),
+        ("an3",     Channel::ChRelAn3),
+        ("an0-an1", Channel::ChDiffAn0An1),
+        ("an0-an3", Channel::ChDiffAn0An3),
+        ("an1-an3", Channel::ChDiffAn1An3),
+        ("an2-an3", Channel::ChDiffAn2An3),
+    ])
+}
+
+fn adc_fsr(field : &str, fsr : f32) -> Result<FullScaleRange, ConfigError> {
+    lookup(field, fsr, &[
+        (6.144, FullScaleRange::Fsr6p144),
+        (4.096, FullScaleRange::Fsr4p096),
+        (2.048, FullScaleRange::Fsr2p048),
+        (1.024, FullScaleRange::Fsr1p024),
+        (0.512, FullScaleRange::Fsr0p512),
+        (0.256, FullScaleRange::Fsr0p256),
+    ])
+}
+
+fn adc_data_rate(field : &str, dr : u16) -> Result<DataRate, ConfigError> {
+    lookup(field, dr as f32, &[
+        (8.0,   DataRate::DataRate8PerSec),
+        (16.0,  DataRate::DataRate16PerSec),
+        (32.0,  DataRate::DataRate32PerSec),
+        (64.0,  DataRate::DataRate64PerSec),
+        (128.0, DataRate::DataRate128PerSec),
+        (250.0, DataRate::DataRate250PerSec),
+        (475.0, DataRate::DataRate475PerSec),
+        (860.0, DataRate::DataRate860PerSec),
+    ])
+}
+
+// Measured voltage over the voltage at the ADC pin
+fn adc_divider(field : &str, divider : f32) -> Result<f32, ConfigError> {
+    if !(divider > 0.0 && divider.is_finite()) {
+        return invalid(field, format!("{} is not a positive ratio", divider));
+    }
+    Ok(divider)
+}
+
+fn mics5524(s : &RawMics5524) -> Result<AdcInput, ConfigError> {
+    Ok(AdcInput {
+        ch      : adc_channel("sensors.mics5524.channel", &s.channel)?,
+        fsr     : adc_fsr("sensors.mics5524.fsr", s.fsr)?,
+        dr      : adc_data_rate("sensors.mics5524.data_rate", s.data_rate)?,
+        divider : adc_divider("sensors.mics5524.divider", s.divider)?,
+    })
+}
+
+fn ads1115(s : &RawAds1115) -> Result<AdcScanConf, ConfigError> {
+    if s.input.is_empty() && s.alert.is_none() {
+        return invalid("sensors.ads1115.input", "must list at least one input, or set sensors.ads1115.alert");
+    }
+    let dr = adc_data_rate("sensors.ads1115.data_rate", s.data_rate)?;
+    let mut inputs : Vec<AdcInput> = Vec::new();
+    for (i, raw) in s.input.iter().enumerate() {
+        let field = |name : &str| format!("sensors.ads1115.input[{}].{}", i, name);
+        let ch = adc_channel(&field("channel"), &raw.channel)?;
+        if inputs.iter().any(|input| input.ch == ch) {
+            return invalid(&field("channel"), format!("\"{}\" is listed twice", raw.channel));
+        }
+        inputs.push(AdcInput {
+            ch,
+            fsr     : adc_fsr(&field("fsr"), raw.fsr)?,
+            dr,
+            divider : adc_divider(&field("divider"), raw.divider)?,
+        });
+    }
+    let comparator = match s.alert {
+        Some(ref a) => {
+            if a.low >= a.high {
+                return invalid("sensors.ads1115.alert.low", "must be below sensors.ads1115.alert.high");
+            }
+            Some(Comparator {
+                input : AdcInput {
+                    ch      : adc_channel("sensors.ads1115.alert.channel", &a.channel)?,
+                    fsr     : adc_fsr("sensors.ads1115.alert.fsr", a.fsr)?,
+                    dr,
+                    divider : adc_divider("sensors.ads1115.alert.divider", a.divider)?,
+                },
+                mode : lookup_str("sensors.ads1115.alert.mode", &a.mode, &[
+                    ("traditional", CompMode::Traditional),
+                    ("window",      CompMode::Window),
+                ])?,
+                low  : a.low,
+                high : a.high,
+                queue : lookup("sensors.ads1115.alert.queue", a.queue as f32, &[
+                    (1.0, CompQueueMode::CompQueue1), (2.0, CompQueueMode::CompQueue2), (4.0, CompQueueMode::CompQueue4),
+                ])?,
+                latching    : a.latch,
+                active_high : a.active_high,
+            })
+        },
+        None => None,
+    };
+    Ok(AdcScanConf {
+        inputs,
+        comparator,
+        alert_gpio : s.alert.as_ref().and_then(|a| a.gpio),
+    })
+}
+
+fn lsm9ds1(s : &RawLsm9ds1) -> Result<ImuConf, ConfigError> {
+    Ok(ImuConf {
+        gyro_odr : lookup("sensors.lsm9ds1.gyro_odr", s.gyro_odr, &[
+            (0.0,   GyroOdr::PowerDown),
+            (14.9,  GyroOdr::Odr14p9Hz),
+            (59.5,  GyroOdr::Odr59p5Hz),
+            (119.0, GyroOdr::Odr119Hz),
+            (238.0, GyroOdr::Odr238Hz),
+            (476.0, GyroOdr::Odr476Hz),
+            (952.0, GyroOdr::Odr952Hz),
+        ])?,
+        gyro_fsr : lookup("sensors.lsm9ds1.gyro_range", s.gyro_range as f32, &[
+            (245.0,  GyroFsr::Fsr245Dps),
+            (500.0,  GyroFsr::Fsr500Dps),
+            (2000.0, GyroFsr::Fsr2000Dps),
+        ])?,
+        accel_odr : lookup("sensors.lsm9ds1.accel_odr", s.accel_odr, &[
+            (0.0,   AccelOdr::PowerDown),
+            (10.0,  AccelOdr::Odr10Hz),
+            (50.0,  AccelOdr::Odr50Hz),
+            (119.0, AccelOdr::Odr119Hz),
+            (238.0, AccelOdr::Odr238Hz),
+            (476.0, AccelOdr::Odr476Hz),
+            (952.0, AccelOdr::Odr952Hz),
+        ])?,
+        accel_fsr : lookup("sensors.lsm9ds1.accel_range", s.accel_range as f32, &[
+            (2.0,  AccelFsr::Fsr2G),
+            (4.0,  AccelFsr::Fsr4G),
+            (8.0,  AccelFsr::Fsr8G),
+            (16.0, AccelFsr::Fsr16G),
+        ])?,
+        mag_odr : lookup("sensors.lsm9ds1.mag_odr", s.mag_odr, &[
+            (0.625, MagOdr::Odr0p625Hz),
+            (1.25,  MagOdr::Odr1p25Hz),
+            (2.5,   MagOdr::Odr2p5Hz),
+            (5.0,   MagOdr::Odr5Hz),
+            (10.0,  MagOdr::Odr10Hz),
+            (20.0,  MagOdr::Odr20Hz),
+            (40.0,  MagOdr::Odr40Hz),
+            (80.0,  MagOdr::Odr80Hz),
+        ])?,
+        mag_fsr : lookup("sensors.lsm9ds1.mag_range", s.mag_range as f32, &[
+            (4.0,  MagFsr::Fsr4Gauss),
+            (8.0,  MagFsr::Fsr8Gauss),
+            (12.0, MagFsr::Fsr12Gauss),
+            (16.0, MagFsr::Fsr16Gauss),
+        ])?,
+        mag_mode : MagMode::Continuous,
+        mag_perf : MagPerf::HighPerf,
+    })
+}
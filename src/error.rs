@@ -0,0 +1,72 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::time::Duration;
+
+use crate::sinks::SinkError;
+
+#[derive(Debug)]
+pub enum Error {
+    // I2C transfer failed, `reg` is the register being accessed if any
+    Bus { sensor : &'static str, reg : Option<u8>, msg : String },
+    // Another chip (or none) answers at the sensor's address
+    IdMismatch { sensor : &'static str, reg : u8, expected : u8, found : u8 },
+    // The data ready flag of `reg` was not set in time
+    Timeout { sensor : &'static str, reg : u8, waited : Duration },
+    // Factory calibration data that cannot be used
+    Calibration { sensor : &'static str, msg : String },
+    // The sensor worker has not reported anything for `silent`
+    Stalled { sensor : String, silent : Duration },
+    Sink { sink : String, err : SinkError },
+    Io(io::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    // Sensor the error comes from, if any
+    pub fn sensor(&self) -> Option<&str> {
+        match self {
+            Error::Bus { sensor, .. } |
+            Error::IdMismatch { sensor, .. } |
+            Error::Timeout { sensor, .. } |
+            Error::Calibration { sensor, .. } => Some(sensor),
+            Error::Stalled { sensor, .. } => Some(sensor),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Bus { sensor, reg : Some(reg), msg } => write!(f, "{} : I2C error on register 0x{:02X} : {}", sensor, reg, msg),
+            Error::Bus { sensor, reg : None, msg } => write!(f, "{} : I2C error : {}", sensor, msg),
+            Error::IdMismatch { sensor, reg, expected, found } =>
+                write!(f, "{} : wrong ID in register 0x{:02X} (=0x{:02X}) != 0x{:02X}", sensor, reg, found, expected),
+            Error::Timeout { sensor, reg, waited } =>
+                write!(f, "{} : no data ready in register 0x{:02X} after {} ms", sensor, reg, waited.as_millis()),
+            Error::Calibration { sensor, msg } => write!(f, "{} : invalid calibration : {}", sensor, msg),
+            Error::Stalled { sensor, silent } => write!(f, "{} : no reading for {} s", sensor, silent.as_secs()),
+            Error::Sink { sink, err } => write!(f, "{} : {}", sink, err),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Sink { err, .. } => Some(err),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e : io::Error) -> Error {
+        Error::Io(e)
+    }
+}
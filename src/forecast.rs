@@ -0,0 +1,238 @@
+use chrono::prelude::*;
+use std::collections::VecDeque;
+use std::io;
+
+use crate::sampling::{Derive, Snapshot};
+use crate::sensors::measurement::{Measurement, Quantity, Unit};
+use crate::storage::{Resolution, Store};
+
+// Short-term weather forecast from the barometric trend over the last 3
+// hours, after the Zambretti forecaster. Works on the sea-level pressure when
+// the station altitude is configured, else on the station pressure, which
+// only gives sensible forecasts close to sea level.
+
+pub const FORECAST_ID_STR : &str = "forecast";
+pub const FORECAST_QUANTITIES : [Quantity; 3] = [Quantity::BaroTrend, Quantity::BaroTendency, Quantity::Forecast];
+
+const WINDOW_S   : i64 = 3 * 3600;
+// History needed before the trend means anything
+const MIN_SPAN_S : i64 = 3600;
+// Change below 1.6 hPa in 3 h counts as steady
+const STEADY_HPA_PER_H : f64 = 1.6 / 3.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tendency {
+    Falling = -1,
+    Steady  = 0,
+    Rising  = 1,
+}
+
+impl Tendency {
+    pub fn from_rate(hpa_per_h : f64) -> Tendency {
+        if hpa_per_h <= -STEADY_HPA_PER_H {
+            Tendency::Falling
+        } else if hpa_per_h >= STEADY_HPA_PER_H {
+            Tendency::Rising
+        } else {
+            Tendency::Steady
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Tendency::Falling => "falling",
+            Tendency::Steady  => "steady",
+            Tendency::Rising  => "rising",
+        }
+    }
+}
+
+// Kind of weather, picks the LED matrix icon
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Outlook {
+    Fine,
+    Fair,
+    Changeable,
+    Rain,
+    Stormy,
+}
+
+// Zambretti forecasts by code : 1-9 falling, 10-19 steady, 20-32 rising
+const FORECASTS : [(&str, Outlook); 32] = [
+    ("Settled fine",                           Outlook::Fine),
+    ("Fine weather",                           Outlook::Fine),
+    ("Fine, becoming less settled",            Outlook::Fair),
+    ("Fairly fine, showery later",             Outlook::Changeable),
+    ("Showery, becoming more unsettled",       Outlook::Rain),
+    ("Unsettled, rain later",                  Outlook::Rain),
+    ("Rain at times, worse later",             Outlook::Rain),
+    ("Rain at times, becoming very unsettled", Outlook::Rain),
+    ("Very unsettled, rain",                   Outlook::Rain),
+    ("Settled fine",                           Outlook::Fine),
+    ("Fine weather",                           Outlook::Fine),
+    ("Fine, possibly showers",                 Outlook::Fair),
+    ("Fairly fine, showers likely",            Outlook::Changeable),
+    ("Showery, bright intervals",              Outlook::Changeable),
+    ("Changeable, some rain",                  Outlook::Changeable),
+    ("Unsettled, rain at times",               Outlook::Rain),
+    ("Rain at frequent intervals",             Outlook::Rain),
+    ("Very unsettled, rain",                   Outlook::Rain),
+    ("Stormy, much rain",                      Outlook::Stormy),
+    ("Settled fine",                           Outlook::Fine),
+    ("Fine weather",                           Outlook::Fine),
+    ("Becoming fine",                          Outlook::Fair),
+    ("Fairly fine, improving",                 Outlook::Fair),
+    ("Fairly fine, possibly showers early",    Outlook::Fair),
+    ("Showery early, improving",               Outlook::Changeable),
+    ("Changeable, mending",                    Outlook::Changeable),
+    ("Rather unsettled, clearing later",       Outlook::Changeable),
+    ("Unsettled, probably improving",          Outlook::Changeable),
+    ("Unsettled, short fine intervals",        Outlook::Rain),
+    ("Very unsettled, finer at times",         Outlook::Rain),
+    ("Stormy, possibly improving",             Outlook::Stormy),
+    ("Stormy, much rain",                      Outlook::Stormy),
+];
+
+// Forecast code for a sea-level pressure in hPa
+pub fn zambretti(hpa : f64, tendency : Tendency) -> u8 {
+    let (z, min, max) = match tendency {
+        Tendency::Falling => (127.0 - 0.12 * hpa, 1.0, 9.0),
+        Tendency::Steady  => (144.0 - 0.13 * hpa, 10.0, 19.0),
+        Tendency::Rising  => (185.0 - 0.16 * hpa, 20.0, 32.0),
+    };
+    z.round().clamp(min, max) as u8
+}
+
+// Text and outlook of a forecast code
+pub fn describe(code : u8) -> Option<(&'static str, Outlook)> {
+    match code {
+        1..=32 => Some(FORECASTS[code as usize - 1]),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Forecast {
+    // Pressure change in hPa/h
+    pub rate     : f64,
+    pub tendency : Tendency,
+    pub code     : u8,
+}
+
+impl Forecast {
+    pub fn text(&self) -> &'static str {
+        FORECASTS[self.code as usize - 1].0
+    }
+
+    pub fn outlook(&self) -> Outlook {
+        FORECASTS[self.code as usize - 1].1
+    }
+}
+
+pub struct Forecaster {
+    // Timestamp in s and pressure in hPa
+    history : VecDeque<(i64, f64)>,
+}
+
+impl Forecaster {
+    pub fn new() -> Forecaster {
+        Forecaster { history : VecDeque::new() }
+    }
+
+    // Adds a pressure in Pa, older readings are ignored
+    pub fn push(&mut self, ts : DateTime<Utc>, pa : f64) {
+        let ts = ts.timestamp();
+        if let Some(&(last, _)) = self.history.back() {
+            if ts <= last {
+                return;
+            }
+        }
+        self.history.push_back((ts, pa / 100.0));
+        while let Some(&(first, _)) = self.history.front() {
+            if ts - first <= WINDOW_S {
+                break;
+            }
+            self.history.pop_front();
+        }
+    }
+
+    // Loads the window before `now` from the stored pressure series, the
+    // one `derive` would use, so the trend survives a restart
+    pub fn seed(&mut self, store : &Store, now : DateTime<Utc>) -> io::Result<()> {
+        let from = now - chrono::Duration::seconds(WINDOW_S);
+        for key in &["pressure_sea_level", "pressure"] {
+            let points = store.query(key, Resolution::Minute, from, now)?;
+            if !points.is_empty() {
+                for p in points {
+                    if let Some(ts) = Utc.timestamp_opt(p.ts, 0).single() {
+                        self.push(ts, p.avg);
+                    }
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Least-squares slope of the history in hPa/h, once it spans an hour
+    pub fn trend(&self) -> Option<f64> {
+        let first = self.history.front()?.0;
+        let last = self.history.back()?.0;
+        if last - first < MIN_SPAN_S {
+            return None;
+        }
+        let n = self.history.len() as f64;
+        let mean_t = self.history.iter().map(|&(t, _)| (t - first) as f64).sum::<f64>() / n;
+        let mean_p = self.history.iter().map(|&(_, p)| p).sum::<f64>() / n;
+        let (mut num, mut den) = (0.0, 0.0);
+        for &(t, p) in &self.history {
+            let dt = (t - first) as f64 - mean_t;
+            num += dt * (p - mean_p);
+            den += dt * dt;
+        }
+        Some(num / den * 3600.0)
+    }
+
+    pub fn forecast(&self) -> Option<Forecast> {
+        let rate = self.trend()?;
+        let tendency = Tendency::from_rate(rate);
+        let &(_, hpa) = self.history.back()?;
+        Some(Forecast { rate, tendency, code : zambretti(hpa, tendency) })
+    }
+}
+
+impl Default for Forecaster {
+    fn default() -> Forecaster {
+        Forecaster::new()
+    }
+}
+
+impl Derive for Forecaster {
+    fn id(&self) -> &str {
+        FORECAST_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &FORECAST_QUANTITIES
+    }
+
+    fn derive(&mut self, current : &Snapshot) -> Vec<Measurement> {
+        let p = match current.get("pressure_sea_level").or_else(|| current.get("pressure")) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        match self.history.back() {
+            Some(&(last, _)) if p.timestamp.timestamp() <= last => return Vec::new(),
+            _ => (),
+        }
+        self.push(p.timestamp, p.value);
+        match self.forecast() {
+            Some(f) => vec![
+                Measurement::new(self.id(), Quantity::BaroTrend,    Unit::HpaPerHour, f.rate),
+                Measurement::new(self.id(), Quantity::BaroTendency, Unit::Code,       f.tendency as i8),
+                Measurement::new(self.id(), Quantity::Forecast,     Unit::Code,       f.code),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::forecast::Outlook;
+use crate::hmi::color::Color;
+use crate::hmi::color::colors::*;
+
+// 8x8 weather icons, one string per row :
+//   Y sun, W cloud, G dark cloud, B rain, P lightning, anything else is off
+
+const FINE : [&str; 8] = [
+    "Y..Y..Y.",
+    ".Y.YY.Y.",
+    "..YYYY..",
+    "YYYYYYYY",
+    "..YYYY..",
+    ".Y.YY.Y.",
+    "Y..YY..Y",
+    "...Y....",
+];
+
+const FAIR : [&str; 8] = [
+    ".Y..Y...",
+    "..YYYY..",
+    ".YYYYYY.",
+    "YYYWWYY.",
+    "..WWWWW.",
+    ".WWWWWWW",
+    ".WWWWWWW",
+    "........",
+];
+
+const CHANGEABLE : [&str; 8] = [
+    "........",
+    "...WW...",
+    "..WWWWW.",
+    ".WWWWWWW",
+    "WWWWWWWW",
+    "........",
+    ".B...B..",
+    "...B...B",
+];
+
+const RAIN : [&str; 8] = [
+    "...GG...",
+    "..GGGGG.",
+    ".GGGGGGG",
+    "GGGGGGGG",
+    "........",
+    "B.B.B.B.",
+    ".B.B.B.B",
+    "B.B.B.B.",
+];
+
+const STORMY : [&str; 8] = [
+    "...GG...",
+    "..GGGGG.",
+    ".GGGGGGG",
+    "GGGGGGGG",
+    "....PP..",
+    "B..PP..B",
+    "..PPPP..",
+    "B...P..B",
+];
+
+// Frame for `Matrix::set_frame`
+pub fn outlook(o : Outlook) -> [Color; 64] {
+    let rows = match o {
+        Outlook::Fine       => &FINE,
+        Outlook::Fair       => &FAIR,
+        Outlook::Changeable => &CHANGEABLE,
+        Outlook::Rain       => &RAIN,
+        Outlook::Stormy     => &STORMY,
+    };
+    let mut frame = [BLACK; 64];
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.bytes().enumerate() {
+            frame[y * 8 + x] = match c {
+                b'Y' => YELLOW,
+                b'W' => WHITE,
+                b'G' => Color(8, 16, 8),
+                b'B' => BLUE,
+                b'P' => PURPLE,
+                _    => BLACK,
+            };
+        }
+    }
+    frame
+}
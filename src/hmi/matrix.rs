@@ -8,7 +8,7 @@ use std::thread;
 use crate::hmi::color::*;
 use crate::hmi::glcdfont::FONT;
 
-#[allow(dead_code)]
+#[derive(Copy, Clone)]
 pub enum Orientation {
     Cw0,
     Cw90,
@@ -74,7 +74,7 @@ impl Matrix {
             Orientation::Cw270 => 2 * (     y  * 8 + (7 - x)),
         };
 
-        self.framebuffer[fb_idx + 0] = color_lsb;
+        self.framebuffer[fb_idx    ] = color_lsb;
         self.framebuffer[fb_idx + 1] = color_msb;
 
         Ok(())
@@ -160,10 +160,8 @@ impl Matrix {
 
 impl Drop for Matrix {
     fn drop(&mut self) {
-        let mut file = OpenOptions::new().write(true).open(&self.file_name).expect("Cannot open /dev/fb1");
-        match file.write_all(&[0;192 * 2]) {
-            Ok(()) => (),
-            Err(_) => (),
-        };
+        if let Ok(mut file) = OpenOptions::new().write(true).open(&self.file_name) {
+            let _ = file.write_all(&[0;192 * 2]);
+        }
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
 pub mod matrix;
 pub mod color;
-pub mod glcdfont;
\ No newline at end of file
+pub mod glcdfont;
+pub mod icons;
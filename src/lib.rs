@@ -0,0 +1,11 @@
+pub mod comfort;
+pub mod config;
+pub mod error;
+pub mod forecast;
+pub mod outbox;
+pub mod sampling;
+pub mod sensors;
+pub mod sinks;
+pub mod storage;
+pub mod web;
+pub mod hmi;
@@ -1,34 +1,39 @@
-mod sensors;
-mod hmi;
-
-use sensors::airq::*;
-use sensors::press_temp::*;
-use sensors::light;
-use sensors::humidity::*;
-use std::thread;
-use std::time::Duration;
+use rpiapptmon::comfort::Comfort;
+use rpiapptmon::config::Config;
+use rpiapptmon::error::Error;
+use rpiapptmon::forecast::{self, Forecaster};
+use rpiapptmon::hmi::icons;
+use rpiapptmon::hmi::matrix::Matrix;
+use rpiapptmon::sensors::ads1115::*;
+use rpiapptmon::sensors::airq::*;
+use rpiapptmon::sensors::press_temp::*;
+use rpiapptmon::sensors::light;
+use rpiapptmon::sensors::humidity::*;
+use rpiapptmon::sensors::imu::*;
+use rpiapptmon::sensors::bus::Bus;
+use rpiapptmon::sensors::measurement::Sensor;
+use rpiapptmon::sampling::{Aggregator, Deferred};
+use rpiapptmon::outbox::{Backoff, Outbox};
+use rpiapptmon::sinks::{self, Forwarder, Sample};
+use rpiapptmon::storage::Store;
+use rpiapptmon::web::{self, WebState};
+use std::env;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use chrono::prelude::*;
-use hmi::matrix::*;
-use hmi::color::*;
-use reqwest::Client;
-use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::Write;
-use serde_json::json;
-use std::collections::HashMap;
 
-const ACCESS_TOKEN : &str = "Rbq9v5HG1b023o2rPcEZ";
-const ERR_CNTR_MAX : u8   = 10;
-const LOG_FILE     : &str = "/home/pi/log.txt";
-
-fn log_to_file<T : Into<String>>(msg : T) -> Result<(), std::io::Error> {
+fn log_to_file<P : AsRef<Path>, T : Into<String>>(path : P, msg : T) -> Result<(), std::io::Error> {
     let now = Local::now();
     let mut file = OpenOptions::new()
                     .append(true)
                     .create(true)
-                    .open(LOG_FILE)?;
+                    .open(path)?;
 
-    writeln!(file, "{} [{}]", 
+    writeln!(file, "{} [{}]",
         now.format("%d/%m/%Y-%H:%M:%S"),
         msg.into(),
     )?;
@@ -36,77 +41,200 @@ fn log_to_file<T : Into<String>>(msg : T) -> Result<(), std::io::Error> {
 }
 
 
-fn main() -> Result<(), std::io::Error> {
-    let hum = HumiditySensor::new(
-        HumAvgCfg::AvgSmplx4, 
-        TempAvgCfg::AvgSmplx4, 
-        OutDataRate::SingleShot, 
-        ctrl1_msks::PWR_UP | ctrl1_msks::BDU_ENA, 
-        ctrl2_msks::ONE_SHOT_EN, 
-        0
-    )?;
+fn main() -> Result<(), Error> {
+    let cfg = match Config::path_from_args(env::args()).and_then(Config::load) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    };
+    let log_file = &cfg.general.log_file;
+    // Opened once, every driver gets a handle on its own address
+    let bus = Bus::open(&cfg.general.i2c_bus)?;
+
+    // Each sensor is read by its own worker at its own interval. Sensors are
+    // set up on their first read : one that is missing or broken is retried
+    // at every interval instead of stopping the monitor. The first sensor
+    // reporting a quantity owns its bare telemetry key.
+    let mut agg = Aggregator::new();
+    let pref = cfg.general.pressure_ref;
+
+    if let Some(oss) = cfg.bmp180 {
+        let bus = bus.clone();
+        agg.spawn(Box::new(Deferred::new(BMP180_ID_STR, &PRESS_TEMP_QUANTITIES, move || {
+            let mut bmp = BMP180::new(bus.device(ADDR))?;
+            bmp.self_test()?;
+            bmp.set_oss(oss);
+            bmp.set_pressure_ref(pref);
+            Ok(Box::new(bmp) as Box<dyn Sensor>)
+        })), cfg.interval(BMP180_ID_STR))?;
+    }
 
-    let mut bmp = BMP180::new()?;
+    if let Some(lpc) = cfg.lps25h {
+        let bus = bus.clone();
+        agg.spawn(Box::new(Deferred::new(LPS25H_ID_STR, &PRESS_TEMP_QUANTITIES, move || {
+            let mut lps = LPS25H::new(bus.device(LPS25H_ADDR), lpc)?;
+            lps.set_pressure_ref(pref);
+            Ok(Box::new(lps) as Box<dyn Sensor>)
+        })), cfg.interval(LPS25H_ID_STR))?;
+    }
 
-    bmp.self_test().unwrap();
+    if let Some(htc) = cfg.hts221 {
+        let bus = bus.clone();
+        agg.spawn(Box::new(Deferred::new(HUM_ID_STR, &HUM_QUANTITIES, move || {
+            let cfg2 = if htc.odr == OutDataRate::SingleShot { ctrl2_msks::ONE_SHOT_EN } else { 0 };
+            let hts = HumiditySensor::new(
+                bus.device(HUM_I2C_ADDR),
+                htc.havg,
+                htc.tavg,
+                htc.odr,
+                ctrl1_msks::PWR_UP | ctrl1_msks::BDU_ENA,
+                cfg2,
+                0
+            )?;
+            Ok(Box::new(hts) as Box<dyn Sensor>)
+        })), cfg.interval(HUM_ID_STR))?;
+    }
 
-    let light = light::Light::new();
+    if let Some(tcc) = cfg.tcs34725 {
+        let bus = bus.clone();
+        agg.spawn(Box::new(Deferred::new(light::LIGHT_ID_STR, &light::LIGHT_QUANTITIES, move || {
+            let tcs = light::Light::new(bus.device(light::LIGHT_I2C_ADDR), tcc)?;
+            Ok(Box::new(tcs) as Box<dyn Sensor>)
+        })), cfg.interval(light::LIGHT_ID_STR))?;
+    }
 
-    let airq = AirQuality::new(
-        AirQualityConf{
-            cfg : misc_cfg::FIRE_SINGLE_CONV,
-            ch : Channel::ChRelAn0,
-            mode : ConvMode::SingleShot,
-            dr : DataRate::DataRate32PerSec,
-            fsr : FullScaleRange::Fsr6p144,
-            comp_mode : CompQueueMode::CompQueueNotActvd,
-        }
-    )?;
+    // The air quality sensor and the plain inputs share the ADC
+    let adc = Ads1115::new(bus.device(ADC_I2C_ADDR)).shared();
+    if let Some(input) = cfg.mics5524 {
+        let adc = adc.clone();
+        agg.spawn(Box::new(Deferred::new(AIRQ_ID_STR, &AIRQ_QUANTITIES, move || {
+            let airq = AirQuality::new(adc.clone(), input);
+            Ok(Box::new(airq) as Box<dyn Sensor>)
+        })), cfg.interval(AIRQ_ID_STR))?;
+    }
+
+    if let Some(ref acc) = cfg.ads1115 {
+        let (adc, acc) = (adc.clone(), acc.clone());
+        agg.spawn(Box::new(Deferred::new(ADC_ID_STR, &ADC_QUANTITIES, move || {
+            let scan = AdcScan::new(adc.clone(), acc.clone())?;
+            Ok(Box::new(scan) as Box<dyn Sensor>)
+        })), cfg.interval(ADC_ID_STR))?;
+    }
 
-    let sndr = Client::new();
+    if let Some(imc) = cfg.lsm9ds1 {
+        let bus = bus.clone();
+        agg.spawn(Box::new(Deferred::new(IMU_ID_STR, &IMU_QUANTITIES, move || {
+            let imu = LSM9DS1::new(
+                bus.device(IMU_AG_I2C_ADDR),
+                bus.device(IMU_MAG_I2C_ADDR),
+                imc,
+            )?;
+            Ok(Box::new(imu) as Box<dyn Sensor>)
+        })), cfg.interval(IMU_ID_STR))?;
+    }
+
+    let store = match cfg.storage {
+        Some(ref sc) => Some(Arc::new(Mutex::new(Store::open(&sc.dir, sc.retention)?))),
+        None => None,
+    };
+
+    // Derived from the pressure readings, needs one of the pressure sensors
+    let mut forecaster = Forecaster::new();
+    if let Some(ref store) = store {
+        if let Err(e) = forecaster.seed(&store.lock().unwrap_or_else(|e| e.into_inner()), Utc::now()) {
+            log_to_file(log_file, format!("Error while reading the pressure history : {:?}", e))?;
+        }
+    }
+    agg.derive(Box::new(forecaster));
+    // Derived from the humidity and temperature readings
+    agg.derive(Box::new(Comfort::new()));
+    let mut matrix = cfg.matrix.as_ref().map(|mc| Matrix::new(&mc.framebuffer, mc.orientation));
+
+    let web_state = Arc::new(WebState::new(store.clone()));
+    if let Some(ref wc) = cfg.web {
+        web::spawn(&wc.listen, web_state.clone())?;
+    }
 
-    let mut cntr = 0;
+    let mut forwarders = Vec::new();
+    for sink in &cfg.telemetry.sinks {
+        let outbox = Outbox::open(cfg.telemetry.outbox_dir.join(format!("{}.jsonl", sink.name)), cfg.telemetry.outbox_max)?;
+        if !outbox.is_empty() {
+            log_to_file(log_file, format!("{} : {} samples left in the outbox from a previous run", sink.name, outbox.len()))?;
+        }
+        let backoff = Backoff::new(cfg.general.sample_interval, cfg.telemetry.retry_max);
+        forwarders.push(Forwarder::new(&sink.name, sinks::open(&sink.conf), outbox, backoff));
+    }
 
+    let mut last_forecast = None;
+    let mut next = Instant::now();
     loop {
-        let light_val = light.get_all();
-
-        let data = json!({
-            "temperature" : bmp.read_temperature()?,
-            "pressure" : bmp.read_pressure(Oss::Oss4)?,
-            "humidity" : hum.get_humidity()?,
-            "light_clear" : light_val.0,
-            "light_red"   : light_val.1,
-            "light_green" : light_val.2,
-            "light_blue"  : light_val.3,
-            "airquality"  : airq.get_ratio_rs_r()?,
-        });
-        let request = format!("http://localhost:8080/api/v1/{}/telemetry", ACCESS_TOKEN);
-        match sndr.post(&request).json(&data).send() {
-            Ok(_) => {
-                cntr = 0;
-            },
-            Err(e) => {
-                cntr += 1;
-                log_to_file(format!("Error (counter = {}) while sending data : {:?}", cntr, e))?;
-                if cntr > ERR_CNTR_MAX {
-                    log_to_file(format!("Error : max (={}) reached", ERR_CNTR_MAX))?;
-                    panic!("Too many errors while attempting to send data to cloud");
-                };
-            },
+        // Readings come in at each sensor's pace, the dashboard follows them
+        next = std::cmp::max(next + cfg.general.sample_interval, Instant::now());
+        while let Some(res) = agg.recv_until(next) {
+            match res {
+                Ok(()) => web_state.set_latest(agg.current()),
+                Err(e) => log_to_file(log_file, format!("Error while reading sensor {}", e))?,
+            }
+        }
+
+        // Every consumer below works on this same snapshot, made of the
+        // readings received since the previous one
+        let (snapshot, errors) = agg.take();
+        for e in errors {
+            log_to_file(log_file, format!("Error while reading sensor {}", e))?;
+        }
+        web_state.set_health(agg.health());
+        // The forecast is derived every sample, it is logged and drawn when
+        // it changes
+        let code = snapshot.get("forecast").map(|m| m.value as u8);
+        if let Some((text, outlook)) = code.filter(|c| Some(*c) != last_forecast).and_then(forecast::describe) {
+            last_forecast = code;
+            log_to_file(log_file, format!("Forecast : {}", text))?;
+            if let Some(ref mut matrix) = matrix {
+                if let Err(e) = matrix.set_frame(&icons::outlook(outlook)).and_then(|_| matrix.flush()) {
+                    log_to_file(log_file, format!("Error while drawing the forecast : {:?}", e))?;
+                }
+            }
+        }
+        if let Some(m) = snapshot.get("light_switch") {
+            let state = if m.value > 0.0 { "on" } else { "off" };
+            log_to_file(log_file, format!("Lights switched {}, seen at {}", state, m.timestamp.with_timezone(&Local).format("%d/%m/%Y-%H:%M:%S")))?;
+        }
+        if let Some(ref store) = store {
+            if let Err(e) = store.lock().unwrap_or_else(|e| e.into_inner()).append_snapshot(&snapshot) {
+                log_to_file(log_file, format!("Error while storing data : {:?}", e))?;
+            }
+        }
+
+        let sample = Sample::from_snapshot(&snapshot);
+        for fwd in forwarders.iter_mut() {
+            if !snapshot.is_empty() {
+                match fwd.push(&sample) {
+                    Ok(0) => (),
+                    Ok(n) => log_to_file(log_file, format!("{} : outbox full, {} oldest samples dropped", fwd.name(), n))?,
+                    Err(e) => log_to_file(log_file, format!("{} : error while queuing data : {:?}", fwd.name(), e))?,
+                }
+            }
+
+            // Replays the outbox in order, stops at the first failure
+            match fwd.flush(Instant::now()) {
+                Ok(flush) => {
+                    if flush.sent > 0 {
+                        log_to_file(log_file, format!("{} : data sent with success ({} samples).", fwd.name(), flush.sent))?;
+                    }
+                    if let Some((e, delay)) = flush.error {
+                        log_to_file(log_file, format!("Error (counter = {}) while sending data, {} samples queued, next attempt in {} s : {}",
+                            fwd.failures(), fwd.queued(), delay.as_secs(), e))?;
+                    }
+                },
+                Err(e) => log_to_file(log_file, format!("{} : error while saving the outbox : {:?}", fwd.name(), e))?,
+            }
+        }
+
+        for m in snapshot.measurements() {
+            log_to_file(log_file, m.to_string())?;
         }
-        log_to_file("Data sent with succes to the cloud.")?;
-
-        log_to_file(format!("temperature : {} *C", bmp.read_temperature()?))?;
-        log_to_file(format!("pressure    : {} Pa", bmp.read_pressure(Oss::Oss4)?))?;
-        log_to_file(format!("humidity    : {} %" , hum.get_humidity()?))?;
-        log_to_file(format!("airquality  : {}"   , airq.get_ratio_rs_r()?))?;
-        log_to_file(format!("light_clear : {}"   , light_val.0))?;
-        log_to_file(format!("light_red   : {}"   , light_val.1))?;
-        log_to_file(format!("light_green : {}"   , light_val.2))?;
-        log_to_file(format!("light_blue  : {}"   , light_val.3))?;
-
-        thread::sleep(Duration::new(30, 0));
     }
-    
-    Ok(())
-}
\ No newline at end of file
+}
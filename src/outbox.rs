@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Persistent FIFO of payloads waiting to be uploaded, one JSON document per
+// line. New payloads are appended to the file; the file is rewritten when
+// entries are removed. Once `max_len` entries are queued the oldest ones are
+// dropped.
+
+pub struct Outbox {
+    path    : PathBuf,
+    max_len : usize,
+    queue   : VecDeque<String>,
+    dirty   : bool,
+}
+
+impl Outbox {
+    pub fn open<P : AsRef<Path>>(path : P, max_len : usize) -> io::Result<Outbox> {
+        let path = path.as_ref().to_owned();
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+
+        let mut queue = VecDeque::new();
+        let mut dirty = false;
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    // A line cut short by a power loss is not valid JSON
+                    if serde_json::from_str::<serde_json::Value>(&line).is_ok() {
+                        queue.push_back(line);
+                    } else {
+                        dirty = true;
+                    }
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+
+        let mut outbox = Outbox { path, max_len, queue, dirty };
+        outbox.trim();
+        outbox.commit()?;
+        Ok(outbox)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // Queues a payload, returns how many old ones had to be dropped
+    pub fn push(&mut self, payload : &serde_json::Value) -> io::Result<usize> {
+        let line = payload.to_string();
+        self.queue.push_back(line.clone());
+        let dropped = self.trim();
+        if dropped > 0 || self.dirty {
+            self.dirty = true;
+            self.commit()?;
+        } else {
+            let mut file = OpenOptions::new().append(true).create(true).open(&self.path)?;
+            writeln!(file, "{}", line)?;
+        }
+        Ok(dropped)
+    }
+
+    pub fn front(&self) -> Option<&str> {
+        self.queue.front().map(String::as_str)
+    }
+
+    // Removes the oldest payload; call `commit` once done removing
+    pub fn pop(&mut self) {
+        if self.queue.pop_front().is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // Writes the queue back to disk if entries were removed
+    pub fn commit(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            for line in &self.queue {
+                writeln!(file, "{}", line)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn trim(&mut self) -> usize {
+        let mut dropped = 0;
+        while self.queue.len() > self.max_len {
+            self.queue.pop_front();
+            dropped += 1;
+        }
+        if dropped > 0 {
+            self.dirty = true;
+        }
+        dropped
+    }
+}
+
+// Exponential backoff between upload attempts : the delay doubles after each
+// failure, from `base` up to `max`, and is reset by a success.
+pub struct Backoff {
+    base     : Duration,
+    max      : Duration,
+    failures : u32,
+    next     : Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(base : Duration, max : Duration) -> Backoff {
+        Backoff { base, max, failures : 0, next : None }
+    }
+
+    pub fn ready(&self, now : Instant) -> bool {
+        match self.next {
+            Some(next) => now >= next,
+            None => true,
+        }
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    // Records a failure, returns the delay before the next attempt
+    pub fn failed(&mut self, now : Instant) -> Duration {
+        let delay = self.base
+            .checked_mul(1 << self.failures.min(16))
+            .map_or(self.max, |d| d.min(self.max));
+        self.failures += 1;
+        self.next = Some(now + delay);
+        delay
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+        self.next = None;
+    }
+}
@@ -0,0 +1,337 @@
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::sensors::measurement::{self, Measurement, Quantity, Sensor};
+
+// Everything read during one cycle. Built once by the `Aggregator` and
+// shared, read only, by every consumer (sinks, store, log, web, display) so
+// they all see the same values.
+#[derive(Debug)]
+pub struct Snapshot {
+    ts           : DateTime<Utc>,
+    measurements : Vec<Measurement>,
+    keys         : Vec<String>,
+    failed       : Vec<String>,
+}
+
+impl Snapshot {
+    pub fn new(ts : DateTime<Utc>, measurements : Vec<Measurement>) -> Snapshot {
+        let keys = measurement::telemetry_keys(&measurements);
+        Snapshot { ts, measurements, keys, failed : Vec::new() }
+    }
+
+    // When the snapshot was taken
+    pub fn ts(&self) -> DateTime<Utc> {
+        self.ts
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.measurements.is_empty()
+    }
+
+    // Ids of the sensors that could not be read this cycle
+    pub fn failed(&self) -> &[String] {
+        &self.failed
+    }
+
+    // Measurements with their telemetry key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Measurement)> {
+        self.keys.iter().map(String::as_str).zip(self.measurements.iter())
+    }
+
+    pub fn get(&self, key : &str) -> Option<&Measurement> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, m)| m)
+    }
+
+    pub fn values(&self) -> Map<String, Value> {
+        self.iter().map(|(k, m)| (k.to_owned(), Value::from(m.value))).collect()
+    }
+}
+
+// Message sent by a worker after each read
+struct Report {
+    worker : usize,
+    result : Result<Vec<Measurement>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Status {
+    // Not read yet
+    Starting,
+    Ok,
+    // Last read failed
+    Failing,
+    // No read reported for more than twice the interval
+    Stalled,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Starting => "starting",
+            Status::Ok       => "ok",
+            Status::Failing  => "failing",
+            Status::Stalled  => "stalled",
+        }
+    }
+}
+
+// Read statistics of one worker
+#[derive(Clone, Debug)]
+pub struct Health {
+    pub sensor             : String,
+    pub interval           : Duration,
+    pub status             : Status,
+    pub reads              : u64,
+    pub errors             : u64,
+    pub consecutive_errors : u32,
+    pub last_ok            : Option<DateTime<Utc>>,
+    pub last_error         : Option<String>,
+    last_report            : Instant,
+}
+
+// Time a worker may stay silent on top of twice its interval, covers the
+// conversion timeouts of a slow sensor
+const STALL_MARGIN : Duration = Duration::from_secs(10);
+
+// Computes extra values from the latest reading of every sensor, e.g. a
+// forecast from the pressure. Called once per snapshot; returns nothing when
+// its inputs did not change since the previous call.
+pub trait Derive {
+    fn id(&self) -> &str;
+
+    // Quantities `derive` can report, in the same order
+    fn quantities(&self) -> &'static [Quantity];
+
+    fn derive(&mut self, current : &Snapshot) -> Vec<Measurement>;
+}
+
+struct Worker {
+    health : Health,
+    latest : Vec<Measurement>,
+    fresh  : bool,
+}
+
+// Runs every sensor on its own thread at its own interval, so a slow or
+// stuck sensor does not delay the others. Workers report over a channel;
+// the aggregator keeps the latest readings and the health of each worker and
+// turns them into snapshots.
+pub struct Aggregator {
+    tx      : Sender<Report>,
+    rx      : Receiver<Report>,
+    workers : Vec<Worker>,
+    derived : Vec<(Box<dyn Derive>, Vec<Measurement>)>,
+    owners  : Vec<(Quantity, String)>,
+}
+
+impl Aggregator {
+    pub fn new() -> Aggregator {
+        let (tx, rx) = mpsc::channel();
+        Aggregator { tx, rx, workers : Vec::new(), derived : Vec::new(), owners : Vec::new() }
+    }
+
+    fn register(&mut self, id : &str, quantities : &[Quantity]) {
+        for q in quantities {
+            if !self.owners.iter().any(|(o, _)| o == q) {
+                self.owners.push((*q, id.to_owned()));
+            }
+        }
+    }
+
+    // Sensors are registered in the order they are spawned, which decides
+    // who owns the bare telemetry keys. The sensor is read right away, then
+    // every `interval`.
+    pub fn spawn(&mut self, mut sensor : Box<dyn Sensor>, interval : Duration) -> io::Result<()> {
+        let id = sensor.id().to_owned();
+        self.register(&id, sensor.quantities());
+
+        let worker = self.workers.len();
+        let tx = self.tx.clone();
+        thread::Builder::new().name(format!("sensor-{}", id)).spawn(move || {
+            let mut next = Instant::now();
+            loop {
+                let result = sensor.measure();
+                if tx.send(Report { worker, result }).is_err() {
+                    return;
+                }
+                next += interval;
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                } else {
+                    // Overran, start again from now instead of catching up
+                    next = now;
+                }
+            }
+        })?;
+
+        self.workers.push(Worker {
+            health : Health {
+                sensor             : id,
+                interval,
+                status             : Status::Starting,
+                reads              : 0,
+                errors             : 0,
+                consecutive_errors : 0,
+                last_ok            : None,
+                last_error         : None,
+                last_report        : Instant::now(),
+            },
+            latest : Vec::new(),
+            fresh  : false,
+        });
+        Ok(())
+    }
+
+    // Derived values are computed in the order they are added, after the
+    // sensor readings, and can use the values derived before them
+    pub fn derive(&mut self, derive : Box<dyn Derive>) {
+        self.register(derive.id(), derive.quantities());
+        self.derived.push((derive, Vec::new()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    // Waits for the next report until `deadline`. Returns `None` once the
+    // deadline is reached, else the outcome of the read.
+    pub fn recv_until(&mut self, deadline : Instant) -> Option<Result<()>> {
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        let report = self.rx.recv_timeout(deadline - now).ok()?;
+        let worker = &mut self.workers[report.worker];
+        let health = &mut worker.health;
+        health.last_report = Instant::now();
+        health.reads += 1;
+        Some(match report.result {
+            Ok(measurements) => {
+                health.status = Status::Ok;
+                health.consecutive_errors = 0;
+                health.last_ok = Some(Utc::now());
+                worker.latest = measurements;
+                worker.fresh = true;
+                Ok(())
+            },
+            Err(e) => {
+                health.status = Status::Failing;
+                health.errors += 1;
+                health.consecutive_errors += 1;
+                health.last_error = Some(e.to_string());
+                Err(e)
+            },
+        })
+    }
+
+    // Latest readings of every sensor and latest derived values, however
+    // old : what the dashboard shows
+    pub fn current(&self) -> Arc<Snapshot> {
+        Arc::new(self.snapshot(self.latest()))
+    }
+
+    fn latest(&self) -> Vec<Measurement> {
+        self.workers.iter().flat_map(|w| w.latest.iter())
+            .chain(self.derived.iter().flat_map(|(_, m)| m.iter()))
+            .cloned()
+            .collect()
+    }
+
+    // Readings received and values derived since the previous call, to be
+    // stored and sent, with an error for each worker that just stalled
+    pub fn take(&mut self) -> (Arc<Snapshot>, Vec<Error>) {
+        let now = Instant::now();
+        let mut measurements = Vec::new();
+        let mut errors = Vec::new();
+        for w in self.workers.iter_mut() {
+            if w.fresh {
+                measurements.extend(w.latest.iter().cloned());
+                w.fresh = false;
+            }
+            let silent = now.duration_since(w.health.last_report);
+            if w.health.status != Status::Stalled && silent > w.health.interval * 2 + STALL_MARGIN {
+                w.health.status = Status::Stalled;
+                errors.push(Error::Stalled { sensor : w.health.sensor.clone(), silent });
+            }
+        }
+
+        for i in 0..self.derived.len() {
+            let current = self.snapshot(self.latest());
+            let m = self.derived[i].0.derive(&current);
+            if !m.is_empty() {
+                measurements.extend(m.iter().cloned());
+                self.derived[i].1 = m;
+            }
+        }
+        (Arc::new(self.snapshot(measurements)), errors)
+    }
+
+    pub fn health(&self) -> Vec<Health> {
+        self.workers.iter().map(|w| w.health.clone()).collect()
+    }
+
+    fn snapshot(&self, measurements : Vec<Measurement>) -> Snapshot {
+        let keys = measurements.iter().map(|m| measurement::telemetry_key(&self.owners, m)).collect();
+        let failed = self.workers.iter()
+            .filter(|w| w.health.status == Status::Failing || w.health.status == Status::Stalled)
+            .map(|w| w.health.sensor.clone())
+            .collect();
+        Snapshot { ts : Utc::now(), measurements, keys, failed }
+    }
+}
+
+impl Default for Aggregator {
+    fn default() -> Aggregator {
+        Aggregator::new()
+    }
+}
+
+// Sensor whose driver is created on first use, and again on the next cycle
+// if that fails, so that a sensor missing at start up is picked up once it
+// answers. Its quantities are known up front so telemetry keys do not depend
+// on it being there.
+pub struct Deferred<F> {
+    id         : &'static str,
+    quantities : &'static [Quantity],
+    init       : F,
+    sensor     : Option<Box<dyn Sensor>>,
+}
+
+impl<F : FnMut() -> Result<Box<dyn Sensor>>> Deferred<F> {
+    pub fn new(id : &'static str, quantities : &'static [Quantity], init : F) -> Deferred<F> {
+        Deferred { id, quantities, init, sensor : None }
+    }
+}
+
+impl<F : FnMut() -> Result<Box<dyn Sensor>> + Send> Sensor for Deferred<F> {
+    fn id(&self) -> &str {
+        self.id
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        self.quantities
+    }
+
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let sensor = match self.sensor.take() {
+            Some(sensor) => sensor,
+            None => (self.init)()?,
+        };
+        self.sensor.get_or_insert(sensor).measure()
+    }
+}
@@ -0,0 +1,372 @@
+use i2cdev::core::*;
+use crate::error::Result;
+use crate::sensors::device::Device;
+use crate::sensors::measurement::{Measurement, Quantity, Sensor, Unit};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const ADC_I2C_ADDR : u16 = 0x48;
+pub const ADC_ID_STR   : &str = "ads1115";
+
+// LSB in V of each full scale range, FSR / 2^15 : codes are signed 16 bits
+const SCALE_6P144_UNIT : f32 = 1.875e-4;
+const SCALE_4P096_UNIT : f32 = 1.25e-4;
+const SCALE_2P048_UNIT : f32 = 6.25e-5;
+const SCALE_1P024_UNIT : f32 = 3.125e-5;
+const SCALE_0P512_UNIT : f32 = 1.5625e-5;
+const SCALE_0P256_UNIT : f32 = 7.8125e-6;
+
+
+mod regs {
+    pub const CONV  : u8 = 0x00;
+    pub const CONF  : u8 = 0x01;
+    pub const LOTHD : u8 = 0x02;
+    pub const HITHD : u8 = 0x03;
+}
+
+pub mod misc_cfg {
+    pub const FIRE_SINGLE_CONV  : u16 = 1 << 15;
+    pub const COMP_MODE_WINDOW  : u16 = 1 << 4;
+    pub const COMP_ALRM_ACTV_HI : u16 = 1 << 3;
+    pub const COMP_LAT_ACTV     : u16 = 1 << 2;
+}
+
+#[derive(Copy, Clone)]
+pub enum ConvStatus {
+    NotOver = 0,
+    Over    = 1 << 15,
+}
+
+#[derive(Copy, Clone)]
+pub enum ConvMode {
+    Continuous = 0,
+    SingleShot = 1 << 8,
+}
+
+#[allow(clippy::identity_op)]
+#[derive(Copy, Clone)]
+pub enum CompQueueMode {
+    CompQueue1        = 0 << 0,
+    CompQueue2        = 1 << 0,
+    CompQueue4        = 2 << 0,
+    CompQueueNotActvd = 3 << 0,
+}
+
+#[derive(Copy, Clone)]
+pub enum DataRate {
+    DataRate8PerSec   = 0 << 5,
+    DataRate16PerSec  = 1 << 5,
+    DataRate32PerSec  = 2 << 5,
+    DataRate64PerSec  = 3 << 5,
+    DataRate128PerSec = 4 << 5,
+    DataRate250PerSec = 5 << 5,
+    DataRate475PerSec = 6 << 5,
+    DataRate860PerSec = 7 << 5,
+}
+
+impl DataRate {
+    // One conversion lasts a data period
+    pub fn conv_time(self) -> Duration {
+        let sps = match self {
+            DataRate::DataRate8PerSec   => 8,
+            DataRate::DataRate16PerSec  => 16,
+            DataRate::DataRate32PerSec  => 32,
+            DataRate::DataRate64PerSec  => 64,
+            DataRate::DataRate128PerSec => 128,
+            DataRate::DataRate250PerSec => 250,
+            DataRate::DataRate475PerSec => 475,
+            DataRate::DataRate860PerSec => 860,
+        };
+        Duration::from_micros(1_000_000 / sps)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channel {
+    ChDiffAn0An1 = 0 << 12,
+    ChDiffAn0An3 = 1 << 12,
+    ChDiffAn1An3 = 2 << 12,
+    ChDiffAn2An3 = 3 << 12,
+    ChRelAn0     = 4 << 12,
+    ChRelAn1     = 5 << 12,
+    ChRelAn2     = 6 << 12,
+    ChRelAn3     = 7 << 12,
+}
+
+impl Channel {
+    pub fn is_differential(self) -> bool {
+        (self as u16) < Channel::ChRelAn0 as u16
+    }
+
+    pub fn quantity(self) -> Quantity {
+        ADC_QUANTITIES[(self as u16 >> 12) as usize]
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum FullScaleRange {
+    Fsr6p144  = 0 << 9,
+    Fsr4p096  = 1 << 9,
+    Fsr2p048  = 2 << 9,
+    Fsr1p024  = 3 << 9,
+    Fsr0p512  = 4 << 9,
+    Fsr0p256  = 5 << 9,
+}
+
+// V per code of an input, at the divider input
+fn scale(input : &AdcInput) -> f32 {
+    let lsb = match input.fsr {
+        FullScaleRange::Fsr6p144 => SCALE_6P144_UNIT,
+        FullScaleRange::Fsr4p096 => SCALE_4P096_UNIT,
+        FullScaleRange::Fsr2p048 => SCALE_2P048_UNIT,
+        FullScaleRange::Fsr1p024 => SCALE_1P024_UNIT,
+        FullScaleRange::Fsr0p512 => SCALE_0P512_UNIT,
+        FullScaleRange::Fsr0p256 => SCALE_0P256_UNIT,
+    };
+    lsb * input.divider
+}
+
+// Conversion code of a voltage on an input, for the thresholds
+fn code(input : &AdcInput, volts : f32) -> i16 {
+    (volts / scale(input)).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+// One input read by a single-shot conversion. `divider` is the ratio of
+// the measured voltage to the one at the ADC pin, 1 when wired directly.
+#[derive(Copy, Clone)]
+pub struct AdcInput {
+    pub ch      : Channel,
+    pub fsr     : FullScaleRange,
+    pub dr      : DataRate,
+    pub divider : f32,
+}
+
+impl AdcInput {
+    pub fn as_bit_msk(&self) -> u16 {
+        misc_cfg::FIRE_SINGLE_CONV |
+        ConvMode::SingleShot as u16 |
+        CompQueueMode::CompQueueNotActvd as u16 |
+        self.ch as u16 |
+        self.dr as u16 |
+        self.fsr as u16
+    }
+}
+
+// Traditional : alert above `high`, until back below `low`.
+// Window : alert while outside `low` .. `high`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompMode {
+    Traditional,
+    Window,
+}
+
+// Comparator watching one input, converted continuously between the reads
+// of the other inputs. Thresholds are in V.
+#[derive(Copy, Clone)]
+pub struct Comparator {
+    pub input       : AdcInput,
+    pub mode        : CompMode,
+    pub low         : f32,
+    pub high        : f32,
+    // Out of range conversions before the alert
+    pub queue       : CompQueueMode,
+    // ALERT/RDY stays asserted until the conversion register is read
+    pub latching    : bool,
+    pub active_high : bool,
+}
+
+impl Comparator {
+    pub fn as_bit_msk(&self) -> u16 {
+        let mut conf = ConvMode::Continuous as u16 |
+            self.input.ch as u16 |
+            self.input.dr as u16 |
+            self.input.fsr as u16 |
+            self.queue as u16;
+        if self.mode == CompMode::Window {
+            conf |= misc_cfg::COMP_MODE_WINDOW;
+        }
+        if self.active_high {
+            conf |= misc_cfg::COMP_ALRM_ACTV_HI;
+        }
+        if self.latching {
+            conf |= misc_cfg::COMP_LAT_ACTV;
+        }
+        conf
+    }
+}
+
+// ALERT/RDY pin wired to a GPIO, read through sysfs
+pub struct AlertPin {
+    value : PathBuf,
+}
+
+impl AlertPin {
+    pub fn open(gpio : u32) -> io::Result<AlertPin> {
+        let dir = PathBuf::from(format!("/sys/class/gpio/gpio{}", gpio));
+        if !dir.exists() {
+            fs::write("/sys/class/gpio/export", gpio.to_string())?;
+        }
+        fs::write(dir.join("direction"), "in")?;
+        Ok(AlertPin::new(dir.join("value")))
+    }
+
+    // Value file of a GPIO already set as an input
+    pub fn new<P : Into<PathBuf>>(value : P) -> AlertPin {
+        AlertPin { value : value.into() }
+    }
+
+    pub fn is_high(&self) -> io::Result<bool> {
+        Ok(fs::read_to_string(&self.value)?.trim() == "1")
+    }
+}
+
+pub struct Ads1115<D : I2CDevice> {
+    dev        : Device<D>,
+    comparator : Option<Comparator>,
+    pin        : Option<AlertPin>,
+    // Alert seen on the pin since the last `take_alert`
+    alerted    : bool,
+}
+
+// An ADC shared by the sensors wired to its inputs, so that each conversion
+// (configure, wait, read) is done as a whole
+pub type SharedAds1115<D> = Arc<Mutex<Ads1115<D>>>;
+
+impl<D : I2CDevice> Ads1115<D> {
+    pub fn new(dev : D) -> Ads1115<D> {
+        Ads1115 { dev : Device::new(ADC_ID_STR, dev), comparator : None, pin : None, alerted : false }
+    }
+
+    fn write_reg(&mut self, reg : u8, val : u16) -> Result<()> {
+        self.dev.smbus_write_i2c_block_data(reg, &[(val >> 8) as u8, val as u8])
+    }
+
+    // Starts the comparator, or stops it with `None`. The alert is only
+    // reported when ALERT/RDY is wired to `pin`.
+    pub fn set_comparator(&mut self, comp : Option<Comparator>, pin : Option<AlertPin>) -> Result<()> {
+        self.comparator = comp;
+        self.pin = pin;
+        self.alerted = false;
+        match comp {
+            Some(comp) => {
+                self.write_reg(regs::LOTHD, code(&comp.input, comp.low) as u16)?;
+                self.write_reg(regs::HITHD, code(&comp.input, comp.high) as u16)?;
+                self.write_reg(regs::CONF, comp.as_bit_msk())
+            },
+            None => self.write_reg(regs::CONF, ConvMode::SingleShot as u16 | CompQueueMode::CompQueueNotActvd as u16),
+        }
+    }
+
+    fn check_alert(&mut self) -> Result<()> {
+        if let (Some(pin), Some(comp)) = (&self.pin, &self.comparator) {
+            if pin.is_high()? == comp.active_high {
+                self.alerted = true;
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the comparator fired since the previous call. Releases a
+    // latched alert.
+    pub fn take_alert(&mut self) -> Result<bool> {
+        self.check_alert()?;
+        let alerted = std::mem::replace(&mut self.alerted, false);
+        if alerted && self.comparator.is_some_and(|c| c.latching) {
+            self.dev.smbus_read_i2c_block_data(regs::CONV, 2)?;
+        }
+        Ok(alerted)
+    }
+
+    pub fn shared(self) -> SharedAds1115<D> {
+        Arc::new(Mutex::new(self))
+    }
+
+    // Signed conversion code, a differential input can be negative
+    pub fn read_raw(&mut self, input : &AdcInput) -> Result<i16> {
+        // Reading the conversion register releases a latched alert
+        self.check_alert()?;
+        self.write_reg(regs::CONF, input.as_bit_msk())?;
+        self.dev.poll(regs::CONF, input.dr.conv_time(), |d| {
+            let status = d.smbus_read_i2c_block_data(regs::CONF, 2)?;
+            let status = ((status[0] as u16) << 8) + (status[1] as u16);
+            Ok(status & (ConvStatus::Over as u16) != 0)
+        })?;
+        let val = self.dev.smbus_read_i2c_block_data(regs::CONV, 2)?;
+        // Back to watching the comparator input
+        if let Some(comp) = self.comparator {
+            self.write_reg(regs::CONF, comp.as_bit_msk())?;
+        }
+        Ok(i16::from_be_bytes([val[0], val[1]]))
+    }
+
+    pub fn read_voltage(&mut self, input : &AdcInput) -> Result<f32> {
+        let raw = self.read_raw(input)?;
+        Ok(raw as f32 * scale(input))
+    }
+
+    // Voltages of `inputs`, one conversion each, in the same order
+    pub fn scan(&mut self, inputs : &[AdcInput]) -> Result<Vec<f32>> {
+        inputs.iter().map(|input| self.read_voltage(input)).collect()
+    }
+}
+
+// Same order as the channel numbers, then the comparator alert
+pub const ADC_QUANTITIES : [Quantity; 9] = [
+    Quantity::AdcAn0An1, Quantity::AdcAn0An3, Quantity::AdcAn1An3, Quantity::AdcAn2An3,
+    Quantity::AdcAn0, Quantity::AdcAn1, Quantity::AdcAn2, Quantity::AdcAn3,
+    Quantity::AdcAlert,
+];
+
+#[derive(Clone)]
+pub struct AdcScanConf {
+    pub inputs     : Vec<AdcInput>,
+    pub comparator : Option<Comparator>,
+    // GPIO wired to ALERT/RDY
+    pub alert_gpio : Option<u32>,
+}
+
+// Voltages of a list of inputs, read every cycle, and the comparator alert
+pub struct AdcScan<D : I2CDevice> {
+    adc    : SharedAds1115<D>,
+    inputs : Vec<AdcInput>,
+    alert  : bool,
+}
+
+impl<D : I2CDevice> AdcScan<D> {
+    pub fn new(adc : SharedAds1115<D>, conf : AdcScanConf) -> Result<AdcScan<D>> {
+        let mut inputs = conf.inputs;
+        inputs.sort_by_key(|input| input.ch as u16);
+        let pin = match conf.alert_gpio {
+            Some(gpio) => Some(AlertPin::open(gpio)?),
+            None => None,
+        };
+        let alert = conf.comparator.is_some() && pin.is_some();
+        adc.lock().unwrap_or_else(|e| e.into_inner()).set_comparator(conf.comparator, pin)?;
+        Ok(AdcScan { adc, inputs, alert })
+    }
+}
+
+impl<D : I2CDevice + Send> Sensor for AdcScan<D> {
+    fn id(&self) -> &str {
+        ADC_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &ADC_QUANTITIES
+    }
+
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let mut adc = self.adc.lock().unwrap_or_else(|e| e.into_inner());
+        let volts = adc.scan(&self.inputs)?;
+        let mut ms : Vec<Measurement> = self.inputs.iter().zip(volts)
+            .map(|(input, v)| Measurement::new(self.id(), input.ch.quantity(), Unit::Volt, v))
+            .collect();
+        if self.alert {
+            ms.push(Measurement::new(self.id(), Quantity::AdcAlert, Unit::Code, adc.take_alert()? as u8));
+        }
+        Ok(ms)
+    }
+}
@@ -0,0 +1,129 @@
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// I2C adapter opened once and shared by every driver. Each driver gets a
+// `BusDevice` bound to its slave address; transfers are serialized by a lock
+// held while the adapter is pointed at that address, so drivers running on
+// different threads cannot interleave.
+
+struct Adapter {
+    dev  : LinuxI2CDevice,
+    // Slave address the adapter currently talks to
+    addr : u16,
+}
+
+#[derive(Clone)]
+pub struct Bus {
+    path    : String,
+    adapter : Arc<Mutex<Adapter>>,
+}
+
+impl Bus {
+    pub fn open(path : &str) -> io::Result<Bus> {
+        let dev = LinuxI2CDevice::new(path, 0).map_err(|e| {
+            let e = io::Error::from(e);
+            io::Error::new(e.kind(), format!("Cannot open I2C bus {} : {}", path, e))
+        })?;
+        Ok(Bus {
+            path    : path.to_owned(),
+            adapter : Arc::new(Mutex::new(Adapter { dev, addr : 0 })),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn device(&self, addr : u16) -> BusDevice {
+        BusDevice { adapter : self.adapter.clone(), addr }
+    }
+}
+
+pub struct BusDevice {
+    adapter : Arc<Mutex<Adapter>>,
+    addr    : u16,
+}
+
+impl BusDevice {
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    // Locks the adapter and points it at this device
+    fn select(&self) -> Result<MutexGuard<'_, Adapter>, LinuxI2CError> {
+        // A driver that panicked mid-transfer leaves nothing half done in
+        // the adapter itself, so a poisoned lock is still usable
+        let mut adapter = self.adapter.lock().unwrap_or_else(|e| e.into_inner());
+        if adapter.addr != self.addr {
+            adapter.dev.set_slave_address(self.addr)?;
+            adapter.addr = self.addr;
+        }
+        Ok(adapter)
+    }
+}
+
+impl I2CDevice for BusDevice {
+    type Error = LinuxI2CError;
+
+    fn read(&mut self, data : &mut [u8]) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.read(data)
+    }
+
+    fn write(&mut self, data : &[u8]) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.write(data)
+    }
+
+    fn smbus_write_quick(&mut self, bit : bool) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.smbus_write_quick(bit)
+    }
+
+    fn smbus_read_byte(&mut self) -> Result<u8, LinuxI2CError> {
+        self.select()?.dev.smbus_read_byte()
+    }
+
+    fn smbus_write_byte(&mut self, value : u8) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.smbus_write_byte(value)
+    }
+
+    fn smbus_read_byte_data(&mut self, register : u8) -> Result<u8, LinuxI2CError> {
+        self.select()?.dev.smbus_read_byte_data(register)
+    }
+
+    fn smbus_write_byte_data(&mut self, register : u8, value : u8) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.smbus_write_byte_data(register, value)
+    }
+
+    fn smbus_read_word_data(&mut self, register : u8) -> Result<u16, LinuxI2CError> {
+        self.select()?.dev.smbus_read_word_data(register)
+    }
+
+    fn smbus_write_word_data(&mut self, register : u8, value : u16) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.smbus_write_word_data(register, value)
+    }
+
+    fn smbus_process_word(&mut self, register : u8, value : u16) -> Result<u16, LinuxI2CError> {
+        self.select()?.dev.smbus_process_word(register, value)
+    }
+
+    fn smbus_read_block_data(&mut self, register : u8) -> Result<Vec<u8>, LinuxI2CError> {
+        self.select()?.dev.smbus_read_block_data(register)
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register : u8, len : u8) -> Result<Vec<u8>, LinuxI2CError> {
+        self.select()?.dev.smbus_read_i2c_block_data(register, len)
+    }
+
+    fn smbus_write_block_data(&mut self, register : u8, values : &[u8]) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.smbus_write_block_data(register, values)
+    }
+
+    fn smbus_write_i2c_block_data(&mut self, register : u8, values : &[u8]) -> Result<(), LinuxI2CError> {
+        self.select()?.dev.smbus_write_i2c_block_data(register, values)
+    }
+
+    fn smbus_process_block(&mut self, register : u8, values : &[u8]) -> Result<Vec<u8>, LinuxI2CError> {
+        self.select()?.dev.smbus_process_block(register, values)
+    }
+}
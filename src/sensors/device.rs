@@ -0,0 +1,141 @@
+use i2cdev::core::I2CDevice;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+// Slack added to twice the expected conversion time before giving up, covers
+// bus latency and scheduling on a loaded Pi
+const POLL_MARGIN   : Duration = Duration::from_millis(50);
+const POLL_MIN      : Duration = Duration::from_millis(1);
+const POLL_MAX      : Duration = Duration::from_millis(10);
+
+// I2C device owned by a sensor driver. Forwards every transfer to the
+// underlying device and turns its errors into `Error::Bus`, naming the sensor
+// and the register involved.
+pub struct Device<D : I2CDevice> {
+    dev    : D,
+    sensor : &'static str,
+}
+
+impl<D : I2CDevice> Device<D> {
+    pub fn new(sensor : &'static str, dev : D) -> Device<D> {
+        Device { dev, sensor }
+    }
+
+    pub fn sensor(&self) -> &'static str {
+        self.sensor
+    }
+
+    pub fn check_id(&mut self, reg : u8, expected : u8) -> Result<()> {
+        let found = self.smbus_read_byte_data(reg)?;
+        if found != expected {
+            return Err(Error::IdMismatch { sensor : self.sensor, reg, expected, found });
+        }
+        Ok(())
+    }
+
+    // Polls the status register `reg` until `ready` says the data is there.
+    // `expected` is the datasheet conversion time : it sets the sleep between
+    // polls and the deadline, after which `Error::Timeout` is returned.
+    pub fn poll<F>(&mut self, reg : u8, expected : Duration, mut ready : F) -> Result<()>
+        where F : FnMut(&mut Device<D>) -> Result<bool>
+    {
+        let start = Instant::now();
+        let deadline = expected * 2 + POLL_MARGIN;
+        let period = (expected / 10).clamp(POLL_MIN, POLL_MAX);
+        loop {
+            if ready(self)? {
+                return Ok(());
+            }
+            let waited = start.elapsed();
+            if waited >= deadline {
+                return Err(Error::Timeout { sensor : self.sensor, reg, waited });
+            }
+            thread::sleep(period);
+        }
+    }
+
+    fn ctx<T>(&self, reg : Option<u8>, res : std::result::Result<T, D::Error>) -> Result<T> {
+        res.map_err(|e| Error::Bus { sensor : self.sensor, reg, msg : e.to_string() })
+    }
+}
+
+impl<D : I2CDevice> I2CDevice for Device<D> {
+    type Error = Error;
+
+    fn read(&mut self, data : &mut [u8]) -> Result<()> {
+        let res = self.dev.read(data);
+        self.ctx(None, res)
+    }
+
+    fn write(&mut self, data : &[u8]) -> Result<()> {
+        let res = self.dev.write(data);
+        self.ctx(data.first().cloned(), res)
+    }
+
+    fn smbus_write_quick(&mut self, bit : bool) -> Result<()> {
+        let res = self.dev.smbus_write_quick(bit);
+        self.ctx(None, res)
+    }
+
+    fn smbus_read_byte(&mut self) -> Result<u8> {
+        let res = self.dev.smbus_read_byte();
+        self.ctx(None, res)
+    }
+
+    fn smbus_write_byte(&mut self, value : u8) -> Result<()> {
+        let res = self.dev.smbus_write_byte(value);
+        self.ctx(None, res)
+    }
+
+    fn smbus_read_byte_data(&mut self, register : u8) -> Result<u8> {
+        let res = self.dev.smbus_read_byte_data(register);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_write_byte_data(&mut self, register : u8, value : u8) -> Result<()> {
+        let res = self.dev.smbus_write_byte_data(register, value);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_read_word_data(&mut self, register : u8) -> Result<u16> {
+        let res = self.dev.smbus_read_word_data(register);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_write_word_data(&mut self, register : u8, value : u16) -> Result<()> {
+        let res = self.dev.smbus_write_word_data(register, value);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_process_word(&mut self, register : u8, value : u16) -> Result<u16> {
+        let res = self.dev.smbus_process_word(register, value);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_read_block_data(&mut self, register : u8) -> Result<Vec<u8>> {
+        let res = self.dev.smbus_read_block_data(register);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register : u8, len : u8) -> Result<Vec<u8>> {
+        let res = self.dev.smbus_read_i2c_block_data(register, len);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_write_block_data(&mut self, register : u8, values : &[u8]) -> Result<()> {
+        let res = self.dev.smbus_write_block_data(register, values);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_write_i2c_block_data(&mut self, register : u8, values : &[u8]) -> Result<()> {
+        let res = self.dev.smbus_write_i2c_block_data(register, values);
+        self.ctx(Some(register), res)
+    }
+
+    fn smbus_process_block(&mut self, register : u8, values : &[u8]) -> Result<Vec<u8>> {
+        let res = self.dev.smbus_process_block(register, values);
+        self.ctx(Some(register), res)
+    }
+}
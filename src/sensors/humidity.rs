@@ -1,5 +1,8 @@
 use i2cdev::core::*;
-use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+use crate::error::{Error, Result};
+use crate::sensors::device::Device;
+use crate::sensors::measurement::{Measurement, Quantity, Sensor, Unit};
+use std::time::Duration;
 
 pub enum Regs {
     Id          = 0x0F,
@@ -27,8 +30,9 @@ pub enum Regs {
     T1OutMsb    = 0x3F,
 }
 
-const HUM_I2C_ADDR : u16 = 0x5F;
+pub const HUM_I2C_ADDR : u16 = 0x5F;
 const HUM_ID_VAL   : u8  = 0xBC;
+pub const HUM_ID_STR   : &str = "hts221";
 
 #[derive(Copy, Clone)]
 pub enum TempAvgCfg {
@@ -67,6 +71,19 @@ pub enum OutDataRate {
     DataRate12p5Hz = 3,
 }
 
+impl OutDataRate {
+    // Time between two samples. A one-shot conversion with the largest
+    // averaging is assumed to take no longer than a sample at 1 Hz.
+    pub fn period(self) -> Duration {
+        match self {
+            OutDataRate::SingleShot     => Duration::from_millis(1000),
+            OutDataRate::DataRate1Hz    => Duration::from_millis(1000),
+            OutDataRate::DataRate7Hz    => Duration::from_millis(143),
+            OutDataRate::DataRate12p5Hz => Duration::from_millis(80),
+        }
+    }
+}
+
 pub mod ctrl2_msks {
     pub const REBOOT        : u8 = 1 << 7;
     pub const HEATER_ON     : u8 = 1 << 1;
@@ -84,28 +101,87 @@ pub mod status_msks {
     pub const TEMP_DRDY : u8 = 1 << 0;
 }
 
-pub struct HumiditySensor {
+// Setting the MSB of the register address reads or writes several registers
+// in a row
+const HUM_AUTO_INC : u8 = 1 << 7;
+
+// Factory calibration : two points mapping raw outputs to *C and %rH,
+// stored in registers 0x30 to 0x3F.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub t0_deg_c  : f32,
+    pub t1_deg_c  : f32,
+    pub t0_out    : i16,
+    pub t1_out    : i16,
+    pub h0_rh     : f32,
+    pub h1_rh     : f32,
+    pub h0_t0_out : i16,
+    pub h1_t0_out : i16,
+}
+
+impl Calibration {
+    fn read<D : I2CDevice>(i2c : &mut Device<D>) -> Result<Calibration> {
+        let raw = i2c.smbus_read_i2c_block_data(HUM_AUTO_INC | Regs::H0rHx2 as u8, 16)?;
+        let at = |reg : Regs| raw[reg as usize - Regs::H0rHx2 as usize];
+        let word = |lsb : Regs, msb : Regs| (((at(msb) as u16) << 8) | (at(lsb) as u16)) as i16;
+
+        let t1_t0_msb = at(Regs::T1T0Msb) as u16;
+        let cal = Calibration {
+            t0_deg_c  : (((t1_t0_msb & 0x03) << 8) | at(Regs::T0DegCx8) as u16) as f32 / 8.0,
+            t1_deg_c  : (((t1_t0_msb & 0x0C) << 6) | at(Regs::T1DegCx8) as u16) as f32 / 8.0,
+            t0_out    : word(Regs::T0OutLsb, Regs::T0OutMsb),
+            t1_out    : word(Regs::T1OutLsb, Regs::T1OutMsb),
+            h0_rh     : at(Regs::H0rHx2) as f32 / 2.0,
+            h1_rh     : at(Regs::H1rhx2) as f32 / 2.0,
+            h0_t0_out : word(Regs::H0T0OutLsb, Regs::H0T0OutMsb),
+            h1_t0_out : word(Regs::H1T0OutLsb, Regs::H1T0OutMsb),
+        };
+
+        if cal.t0_out == cal.t1_out {
+            return Err(Error::Calibration { sensor : HUM_ID_STR, msg : "T0_OUT == T1_OUT".to_owned() });
+        }
+        if cal.h0_t0_out == cal.h1_t0_out {
+            return Err(Error::Calibration { sensor : HUM_ID_STR, msg : "H0_T0_OUT == H1_T0_OUT".to_owned() });
+        }
+        Ok(cal)
+    }
+
+    pub fn temperature(&self, t_out : i16) -> f32 {
+        let (t0_out, t1_out) = (self.t0_out as f32, self.t1_out as f32);
+        self.t0_deg_c + (t_out as f32 - t0_out) * (self.t1_deg_c - self.t0_deg_c) / (t1_out - t0_out)
+    }
+
+    pub fn humidity(&self, h_out : i16) -> f32 {
+        let (h0_out, h1_out) = (self.h0_t0_out as f32, self.h1_t0_out as f32);
+        self.h0_rh + (h_out as f32 - h0_out) * (self.h1_rh - self.h0_rh) / (h1_out - h0_out)
+    }
+}
+
+#[allow(dead_code)]
+pub struct HumiditySensor<D : I2CDevice> {
+    i2c  : Device<D>,
     havg : HumAvgCfg,
     tavg : TempAvgCfg,
     odr  : OutDataRate,
     cfg1 : u8,
     cfg2 : u8,
     cfg3 : u8,
+    cal  : Calibration,
 }
 
-impl HumiditySensor {
+impl<D : I2CDevice> HumiditySensor<D> {
     pub fn new(
+        i2c  : D,
         havg : HumAvgCfg, 
         tavg : TempAvgCfg, 
         odr  : OutDataRate,
         cfg1 : u8, 
         cfg2 : u8, 
         cfg3 : u8
-    ) -> Result<HumiditySensor, LinuxI2CError> {
+    ) -> Result<HumiditySensor<D>> {
     
-        let mut i2c = LinuxI2CDevice::new("/dev/i2c-1", HUM_I2C_ADDR)?;
-        let id = i2c.smbus_read_byte_data(Regs::Id as u8)?;
-        if id != HUM_ID_VAL { panic!("Cannot read humidity ID"); }
+        let mut i2c = Device::new(HUM_ID_STR, i2c);
+        i2c.check_id(Regs::Id as u8, HUM_ID_VAL)?;
 
         let cfg = (cfg1 | (odr as u8)) & 0x87;
         i2c.smbus_write_byte_data(Regs::CtrlReg1 as u8, cfg)?;
@@ -117,98 +193,77 @@ impl HumiditySensor {
         let avg = ((tavg as u8) << 3 | (havg as u8)) & 0x3F;
         i2c.smbus_write_byte_data(Regs::AvConf as u8, avg)?;
 
+        let cal = Calibration::read(&mut i2c)?;
         
         Ok(
             HumiditySensor {
+                i2c,
                 havg,
                 tavg,
                 odr,
                 cfg1,
                 cfg2,
                 cfg3,
+                cal,
             }
         )
     }
 
-    pub fn get_temperature(&self) -> Result<f32, LinuxI2CError> {
-        let mut i2c = LinuxI2CDevice::new("/dev/i2c-1", HUM_I2C_ADDR)?;
-
-        let cfg = self.cfg1 | ctrl1_msks::PWR_UP;
-        i2c.smbus_write_byte_data(Regs::CtrlReg1 as u8, cfg)?;
-        
-        let t0_deg_c = i2c.smbus_read_byte_data(Regs::T0DegCx8 as u8)?;
-        let t1_deg_c = i2c.smbus_read_byte_data(Regs::T1DegCx8 as u8)?;
-        let t10_deg_c_msb = i2c.smbus_read_byte_data(Regs::T1T0Msb as u8)?;
-        let t0_deg_c = ((( (t10_deg_c_msb & 0x03)       as u16) << 8) + (t0_deg_c as u16)) as f32 / 8.0;
-        let t1_deg_c = (((((t10_deg_c_msb & 0x0C) >> 2) as u16) << 8) + (t1_deg_c as u16)) as f32 / 8.0;
-
-        let t0_outlsb = i2c.smbus_read_byte_data(Regs::T0OutLsb as u8)?;
-        let t0_outmsb = i2c.smbus_read_byte_data(Regs::T0OutMsb as u8)?;
-
-        let t0_out = (((t0_outmsb as u16) << 8) + (t0_outlsb as u16)) as f32 / 8.0;
-
-        let t1_outlsb = i2c.smbus_read_byte_data(Regs::T1OutLsb as u8)?;
-        let t1_outmsb = i2c.smbus_read_byte_data(Regs::T1OutMsb as u8)?;
+    pub fn calibration(&self) -> &Calibration {
+        &self.cal
+    }
 
-        let t1_out = (((t1_outmsb as u16) << 8) + (t1_outlsb as u16)) as f32 / 8.0;
+    // Reads the calibration registers again, e.g. after a reboot of the
+    // memory content (ctrl2_msks::REBOOT)
+    pub fn reload_calibration(&mut self) -> Result<()> {
+        self.cal = Calibration::read(&mut self.i2c)?;
+        Ok(())
+    }
 
+    // Temperature in *C and relative humidity in % from the same conversion
+    pub fn read(&mut self) -> Result<(f32, f32)> {
+        let i2c = &mut self.i2c;
         if self.odr == OutDataRate::SingleShot {
             let cfg = self.cfg2 | ctrl2_msks::ONE_SHOT_EN;
             i2c.smbus_write_byte_data(Regs::CtrlReg2 as u8, cfg)?;
         }
 
-        loop {
-            let status = i2c.smbus_read_byte_data(Regs::Status as u8)?;
-            if status & status_msks::TEMP_DRDY != 0 { break; }
-        }
-
-        let tout_lsb = i2c.smbus_read_byte_data(Regs::TempOutLo as u8)?;
-        let tout_msb = i2c.smbus_read_byte_data(Regs::TempOutHi as u8)?;
-        let tout = ((((tout_msb as u16) << 8) + (tout_lsb as u16)) as i16) as f32 / 8.0;
+        let drdy = status_msks::HUM_DRDY | status_msks::TEMP_DRDY;
+        i2c.poll(Regs::Status as u8, self.odr.period(), |d| {
+            Ok(d.smbus_read_byte_data(Regs::Status as u8)? & drdy == drdy)
+        })?;
 
-        let a  = (t1_deg_c - t0_deg_c) / (t1_out - t0_out);
-        let b  = ((t1_out * t0_deg_c) - (t0_out * t1_deg_c)) / (t1_out - t0_out);
-        let t_float = a * tout + b;
-
-        Ok(t_float)
+        let raw = i2c.smbus_read_i2c_block_data(HUM_AUTO_INC | Regs::HumOutLo as u8, 4)?;
+        let h_out = (((raw[1] as u16) << 8) | (raw[0] as u16)) as i16;
+        let t_out = (((raw[3] as u16) << 8) | (raw[2] as u16)) as i16;
+        Ok((self.cal.temperature(t_out), self.cal.humidity(h_out)))
     }
 
-    pub fn get_humidity(&self) -> Result<f32, LinuxI2CError> {
-        let mut i2c = LinuxI2CDevice::new("/dev/i2c-1", HUM_I2C_ADDR)?;
-
-        let cfg = self.cfg1 | ctrl1_msks::PWR_UP;
-        i2c.smbus_write_byte_data(Regs::CtrlReg1 as u8, cfg)?;
-
-        let h0_out =  (((i2c.smbus_read_byte_data(Regs::H0T0OutMsb as u8)? as u16) << 8) +
-                        (i2c.smbus_read_byte_data(Regs::H0T0OutLsb as u8)? as u16     ))   as i16 as f32;
-        let h1_out =  (((i2c.smbus_read_byte_data(Regs::H1T0OutMsb as u8)? as u16) << 8) +
-                        (i2c.smbus_read_byte_data(Regs::H1T0OutLsb as u8)? as u16     ))   as i16 as f32;
-
-        let h0_rh = i2c.smbus_read_byte_data(Regs::H0rHx2 as u8)? as f32 / 2.0;
-        let h1_rh = i2c.smbus_read_byte_data(Regs::H1rhx2 as u8)? as f32 / 2.0;
-
-        let status = i2c.smbus_read_byte_data(Regs::Status as u8)?;
-        if status & status_msks::TEMP_DRDY != 0 {
-            let _ = i2c.smbus_read_byte_data(Regs::TempOutHi as u8)?;
-        }
+    pub fn get_temperature(&mut self) -> Result<f32> {
+        Ok(self.read()?.0)
+    }
 
-        if self.odr == OutDataRate::SingleShot {
-            let cfg = self.cfg2 | ctrl2_msks::ONE_SHOT_EN;
-            i2c.smbus_write_byte_data(Regs::CtrlReg2 as u8, cfg)?;
-        }
+    pub fn get_humidity(&mut self) -> Result<f32> {
+        Ok(self.read()?.1)
+    }
+}
 
-        loop {
-            let status = i2c.smbus_read_byte_data(Regs::Status as u8)?;
-            if status & status_msks::HUM_DRDY != 0 { break; }
-        }
+pub const HUM_QUANTITIES : [Quantity; 2] = [Quantity::Humidity, Quantity::Temperature];
 
-        let h_out = (((i2c.smbus_read_byte_data(Regs::HumOutHi as u8)? as u16) << 8) +
-                      (i2c.smbus_read_byte_data(Regs::HumOutLo as u8)? as u16)     ) as i16 as f32;
+impl<D : I2CDevice + Send> Sensor for HumiditySensor<D> {
+    fn id(&self) -> &str {
+        HUM_ID_STR
+    }
 
-        let a = (h1_rh - h0_rh) / (h1_out - h0_out);
-        let b = ((h1_out * h0_rh) - (h0_out * h1_rh)) / (h1_out - h0_out);
-        let h_float = a * h_out + b;
+    fn quantities(&self) -> &'static [Quantity] {
+        &HUM_QUANTITIES
+    }
 
-        Ok(h_float)
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let (t, h) = self.read()?;
+        Ok(vec![
+            Measurement::new(self.id(), Quantity::Humidity,    Unit::Percent, h),
+            Measurement::new(self.id(), Quantity::Temperature, Unit::DegC,    t),
+        ])
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,375 @@
+use i2cdev::core::*;
+use crate::error::Result;
+use crate::sensors::device::Device;
+use crate::sensors::measurement::{Measurement, Quantity, Sensor, Unit};
+use std::time::Duration;
+
+pub const IMU_AG_I2C_ADDR  : u16 = 0x6A;
+pub const IMU_MAG_I2C_ADDR : u16 = 0x1C;
+
+const AG_ID_VAL  : u8 = 0x68;
+const MAG_ID_VAL : u8 = 0x3D;
+pub const IMU_ID_STR : &str = "lsm9ds1";
+
+pub enum AgRegs {
+    WhoAmI     = 0x0F,
+    CtrlReg1G  = 0x10,
+    CtrlReg2G  = 0x11,
+    CtrlReg3G  = 0x12,
+    OutTempL   = 0x15,
+    OutTempH   = 0x16,
+    Status     = 0x17,
+    OutXLG     = 0x18,
+    CtrlReg4   = 0x1E,
+    CtrlReg5Xl = 0x1F,
+    CtrlReg6Xl = 0x20,
+    CtrlReg7Xl = 0x21,
+    CtrlReg8   = 0x22,
+    OutXLXl    = 0x28,
+}
+
+pub enum MagRegs {
+    WhoAmI    = 0x0F,
+    CtrlReg1M = 0x20,
+    CtrlReg2M = 0x21,
+    CtrlReg3M = 0x22,
+    CtrlReg4M = 0x23,
+    CtrlReg5M = 0x24,
+    StatusM   = 0x27,
+    OutXLM    = 0x28,
+}
+
+// The magnetometer only auto-increments the sub-address when its MSB is set.
+const MAG_AUTO_INC : u8 = 1 << 7;
+
+pub mod ag_status_msks {
+    pub const XLDA : u8 = 1 << 0;
+    pub const GDA  : u8 = 1 << 1;
+    pub const TDA  : u8 = 1 << 2;
+}
+
+pub mod mag_status_msks {
+    pub const ZYXDA : u8 = 1 << 3;
+}
+
+pub mod ctrl8_msks {
+    pub const BOOT       : u8 = 1 << 7;
+    pub const BDU        : u8 = 1 << 6;
+    pub const IF_ADD_INC : u8 = 1 << 2;
+    pub const SW_RESET   : u8 = 1 << 0;
+}
+
+const AXES_ENA_MSK : u8 = 0x38;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum GyroOdr {
+    PowerDown  = 0 << 5,
+    Odr14p9Hz  = 1 << 5,
+    Odr59p5Hz  = 2 << 5,
+    Odr119Hz   = 3 << 5,
+    Odr238Hz   = 4 << 5,
+    Odr476Hz   = 5 << 5,
+    Odr952Hz   = 6 << 5,
+}
+
+impl GyroOdr {
+    pub fn period(self) -> Option<Duration> {
+        let mhz = match self {
+            GyroOdr::PowerDown => return None,
+            GyroOdr::Odr14p9Hz => 14_900,
+            GyroOdr::Odr59p5Hz => 59_500,
+            GyroOdr::Odr119Hz  => 119_000,
+            GyroOdr::Odr238Hz  => 238_000,
+            GyroOdr::Odr476Hz  => 476_000,
+            GyroOdr::Odr952Hz  => 952_000,
+        };
+        Some(Duration::from_micros(1_000_000_000 / mhz))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum GyroFsr {
+    Fsr245Dps  = 0 << 3,
+    Fsr500Dps  = 1 << 3,
+    Fsr2000Dps = 3 << 3,
+}
+
+impl GyroFsr {
+    pub fn dps_per_lsb(self) -> f32 {
+        match self {
+            GyroFsr::Fsr245Dps  => 8.75e-3,
+            GyroFsr::Fsr500Dps  => 17.5e-3,
+            GyroFsr::Fsr2000Dps => 70.0e-3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum AccelOdr {
+    PowerDown  = 0 << 5,
+    Odr10Hz    = 1 << 5,
+    Odr50Hz    = 2 << 5,
+    Odr119Hz   = 3 << 5,
+    Odr238Hz   = 4 << 5,
+    Odr476Hz   = 5 << 5,
+    Odr952Hz   = 6 << 5,
+}
+
+impl AccelOdr {
+    pub fn period(self) -> Option<Duration> {
+        let hz = match self {
+            AccelOdr::PowerDown => return None,
+            AccelOdr::Odr10Hz   => 10,
+            AccelOdr::Odr50Hz   => 50,
+            AccelOdr::Odr119Hz  => 119,
+            AccelOdr::Odr238Hz  => 238,
+            AccelOdr::Odr476Hz  => 476,
+            AccelOdr::Odr952Hz  => 952,
+        };
+        Some(Duration::from_micros(1_000_000 / hz))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum AccelFsr {
+    Fsr2G  = 0 << 3,
+    Fsr16G = 1 << 3,
+    Fsr4G  = 2 << 3,
+    Fsr8G  = 3 << 3,
+}
+
+impl AccelFsr {
+    pub fn g_per_lsb(self) -> f32 {
+        match self {
+            AccelFsr::Fsr2G  => 0.061e-3,
+            AccelFsr::Fsr4G  => 0.122e-3,
+            AccelFsr::Fsr8G  => 0.244e-3,
+            AccelFsr::Fsr16G => 0.732e-3,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum MagOdr {
+    Odr0p625Hz = 0 << 2,
+    Odr1p25Hz  = 1 << 2,
+    Odr2p5Hz   = 2 << 2,
+    Odr5Hz     = 3 << 2,
+    Odr10Hz    = 4 << 2,
+    Odr20Hz    = 5 << 2,
+    Odr40Hz    = 6 << 2,
+    Odr80Hz    = 7 << 2,
+}
+
+impl MagOdr {
+    pub fn period(self) -> Duration {
+        let mhz = match self {
+            MagOdr::Odr0p625Hz => 625,
+            MagOdr::Odr1p25Hz  => 1_250,
+            MagOdr::Odr2p5Hz   => 2_500,
+            MagOdr::Odr5Hz     => 5_000,
+            MagOdr::Odr10Hz    => 10_000,
+            MagOdr::Odr20Hz    => 20_000,
+            MagOdr::Odr40Hz    => 40_000,
+            MagOdr::Odr80Hz    => 80_000,
+        };
+        Duration::from_micros(1_000_000_000 / mhz)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum MagFsr {
+    Fsr4Gauss  = 0 << 5,
+    Fsr8Gauss  = 1 << 5,
+    Fsr12Gauss = 2 << 5,
+    Fsr16Gauss = 3 << 5,
+}
+
+impl MagFsr {
+    pub fn gauss_per_lsb(self) -> f32 {
+        match self {
+            MagFsr::Fsr4Gauss  => 0.14e-3,
+            MagFsr::Fsr8Gauss  => 0.29e-3,
+            MagFsr::Fsr12Gauss => 0.43e-3,
+            MagFsr::Fsr16Gauss => 0.58e-3,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum MagMode {
+    Continuous = 0,
+    SingleShot = 1,
+    PowerDown  = 3,
+}
+
+// X/Y operative mode goes to CTRL_REG1_M, Z to CTRL_REG4_M.
+#[derive(Copy, Clone)]
+pub enum MagPerf {
+    LowPower      = 0,
+    MediumPerf    = 1,
+    HighPerf      = 2,
+    UltraHighPerf = 3,
+}
+
+#[derive(Copy, Clone)]
+pub struct ImuConf {
+    pub gyro_odr  : GyroOdr,
+    pub gyro_fsr  : GyroFsr,
+    pub accel_odr : AccelOdr,
+    pub accel_fsr : AccelFsr,
+    pub mag_odr   : MagOdr,
+    pub mag_fsr   : MagFsr,
+    pub mag_mode  : MagMode,
+    pub mag_perf  : MagPerf,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vector3 {
+    pub x : f32,
+    pub y : f32,
+    pub z : f32,
+}
+
+impl Vector3 {
+    pub fn norm(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+}
+
+fn to_vector(raw : &[u8], scale : f32) -> Vector3 {
+    let axis = |i : usize| (((raw[2 * i + 1] as u16) << 8) | (raw[2 * i] as u16)) as i16 as f32 * scale;
+    Vector3 {
+        x : axis(0),
+        y : axis(1),
+        z : axis(2),
+    }
+}
+
+pub struct LSM9DS1<D : I2CDevice> {
+    ag  : Device<D>,
+    mag : Device<D>,
+    imc : ImuConf,
+}
+
+impl<D : I2CDevice> LSM9DS1<D> {
+    pub fn new(ag : D, mag : D, imc : ImuConf) -> Result<LSM9DS1<D>> {
+        let mut ag = Device::new(IMU_ID_STR, ag);
+        let mut mag = Device::new(IMU_ID_STR, mag);
+        ag.check_id(AgRegs::WhoAmI as u8, AG_ID_VAL)?;
+        mag.check_id(MagRegs::WhoAmI as u8, MAG_ID_VAL)?;
+
+        let mut imu = LSM9DS1 { ag, mag, imc };
+        imu.configure(imc)?;
+        Ok(imu)
+    }
+
+    // With the gyroscope running, the accelerometer shares its ODR and
+    // `accel_odr` only applies when `gyro_odr` is `PowerDown`.
+    pub fn configure(&mut self, imc : ImuConf) -> Result<()> {
+        self.ag.smbus_write_byte_data(AgRegs::CtrlReg8 as u8, ctrl8_msks::BDU | ctrl8_msks::IF_ADD_INC)?;
+
+        self.ag.smbus_write_byte_data(AgRegs::CtrlReg4 as u8, AXES_ENA_MSK)?;
+        self.ag.smbus_write_byte_data(AgRegs::CtrlReg1G as u8, imc.gyro_odr as u8 | imc.gyro_fsr as u8)?;
+
+        self.ag.smbus_write_byte_data(AgRegs::CtrlReg5Xl as u8, AXES_ENA_MSK)?;
+        self.ag.smbus_write_byte_data(AgRegs::CtrlReg6Xl as u8, imc.accel_odr as u8 | imc.accel_fsr as u8)?;
+
+        self.mag.smbus_write_byte_data(MagRegs::CtrlReg1M as u8, ((imc.mag_perf as u8) << 5) | imc.mag_odr as u8)?;
+        self.mag.smbus_write_byte_data(MagRegs::CtrlReg2M as u8, imc.mag_fsr as u8)?;
+        self.mag.smbus_write_byte_data(MagRegs::CtrlReg4M as u8, (imc.mag_perf as u8) << 2)?;
+        self.mag.smbus_write_byte_data(MagRegs::CtrlReg3M as u8, imc.mag_mode as u8)?;
+
+        self.imc = imc;
+        Ok(())
+    }
+
+    // Accelerometer and gyroscope share the gyroscope ODR while it runs.
+    // Both powered down, wait as long as for the slowest rate.
+    fn ag_period(&self) -> Duration {
+        self.imc.gyro_odr.period()
+            .or_else(|| self.imc.accel_odr.period())
+            .unwrap_or(Duration::from_millis(100))
+    }
+
+    fn wait_ag(&mut self, msk : u8) -> Result<()> {
+        let expected = self.ag_period();
+        self.ag.poll(AgRegs::Status as u8, expected, |d| {
+            Ok(d.smbus_read_byte_data(AgRegs::Status as u8)? & msk != 0)
+        })
+    }
+
+    // Acceleration in g
+    pub fn read_accel(&mut self) -> Result<Vector3> {
+        self.wait_ag(ag_status_msks::XLDA)?;
+        let raw = self.ag.smbus_read_i2c_block_data(AgRegs::OutXLXl as u8, 6)?;
+        Ok(to_vector(&raw, self.imc.accel_fsr.g_per_lsb()))
+    }
+
+    // Angular rate in dps
+    pub fn read_gyro(&mut self) -> Result<Vector3> {
+        self.wait_ag(ag_status_msks::GDA)?;
+        let raw = self.ag.smbus_read_i2c_block_data(AgRegs::OutXLG as u8, 6)?;
+        Ok(to_vector(&raw, self.imc.gyro_fsr.dps_per_lsb()))
+    }
+
+    // Magnetic field in gauss. In single-shot mode a new conversion is fired
+    // before reading.
+    pub fn read_mag(&mut self) -> Result<Vector3> {
+        if let MagMode::SingleShot = self.imc.mag_mode {
+            self.mag.smbus_write_byte_data(MagRegs::CtrlReg3M as u8, MagMode::SingleShot as u8)?;
+        }
+        self.mag.poll(MagRegs::StatusM as u8, self.imc.mag_odr.period(), |d| {
+            Ok(d.smbus_read_byte_data(MagRegs::StatusM as u8)? & mag_status_msks::ZYXDA != 0)
+        })?;
+        let raw = self.mag.smbus_read_i2c_block_data(MAG_AUTO_INC | MagRegs::OutXLM as u8, 6)?;
+        Ok(to_vector(&raw, self.imc.mag_fsr.gauss_per_lsb()))
+    }
+
+    // Die temperature in *C (16 LSB/*C, 0 at 25 *C)
+    pub fn read_temperature(&mut self) -> Result<f32> {
+        self.wait_ag(ag_status_msks::TDA)?;
+        let raw = self.ag.smbus_read_i2c_block_data(AgRegs::OutTempL as u8, 2)?;
+        let t = (((raw[1] as u16) << 8) | (raw[0] as u16)) as i16;
+        Ok(25.0 + (t as f32) / 16.0)
+    }
+}
+
+pub const IMU_QUANTITIES : [Quantity; 9] = [
+    Quantity::AccelX, Quantity::AccelY, Quantity::AccelZ,
+    Quantity::GyroX,  Quantity::GyroY,  Quantity::GyroZ,
+    Quantity::MagX,   Quantity::MagY,   Quantity::MagZ,
+];
+
+impl<D : I2CDevice + Send> Sensor for LSM9DS1<D> {
+    fn id(&self) -> &str {
+        IMU_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &IMU_QUANTITIES
+    }
+
+    // A powered down gyroscope never sets its data ready flag, it is left out
+    // (and so is the accelerometer when both are powered down)
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let mut ms = Vec::with_capacity(IMU_QUANTITIES.len());
+        if self.imc.gyro_odr != GyroOdr::PowerDown || self.imc.accel_odr != AccelOdr::PowerDown {
+            let a = self.read_accel()?;
+            ms.push(Measurement::new(self.id(), Quantity::AccelX, Unit::G, a.x));
+            ms.push(Measurement::new(self.id(), Quantity::AccelY, Unit::G, a.y));
+            ms.push(Measurement::new(self.id(), Quantity::AccelZ, Unit::G, a.z));
+        }
+        if self.imc.gyro_odr != GyroOdr::PowerDown {
+            let g = self.read_gyro()?;
+            ms.push(Measurement::new(self.id(), Quantity::GyroX, Unit::Dps, g.x));
+            ms.push(Measurement::new(self.id(), Quantity::GyroY, Unit::Dps, g.y));
+            ms.push(Measurement::new(self.id(), Quantity::GyroZ, Unit::Dps, g.z));
+        }
+        let m = self.read_mag()?;
+        ms.push(Measurement::new(self.id(), Quantity::MagX, Unit::Gauss, m.x));
+        ms.push(Measurement::new(self.id(), Quantity::MagY, Unit::Gauss, m.y));
+        ms.push(Measurement::new(self.id(), Quantity::MagZ, Unit::Gauss, m.z));
+        Ok(ms)
+    }
+}
@@ -1,10 +1,12 @@
 use i2cdev::core::*;
-use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+use crate::error::Result;
+use crate::sensors::device::Device;
+use crate::sensors::measurement::{Measurement, Quantity, Sensor, Unit};
 use std::thread;
 use std::time::Duration;
 
 
-const LIGHT_I2C_ADDR : u16 = 0x29;
+pub const LIGHT_I2C_ADDR : u16 = 0x29;
 pub enum Registers {
     Enable  = 0x00,
     Atime   = 0x01,
@@ -31,7 +33,7 @@ pub enum Registers {
 const LIGHT_REG_CMD_MSK        : u8 = 1 << 7;
 const LIGHT_CMD_PRTCL_BYTE     : u8 = 0 << 5;
 const LIGHT_CMD_PRTCL_AUTO_INC : u8 = 1 << 5;
-const LIGHT_CMD_PRTCL_SPEC_FN  : u8 = 2 << 5;
+const LIGHT_CMD_PRTCL_SPEC_FN  : u8 = 3 << 5;
 const LIGHT_SPEC_FN_CLEAR_IRQ  : u8 = 3 << 1;
 
 const LIGHT_ENA_MSK_IRQ_EN     : u8 = 1 << 4;
@@ -41,6 +43,7 @@ const LIGHT_ENA_MSK_PWR_EN     : u8 = 1 << 0;
 
 const LIGHT_CFG_MSK_WLONG_EN   : u8 = 1 << 1;
 
+#[derive(Copy, Clone)]
 pub enum Gain {
     X1  = 0,
     X4  = 1,
@@ -48,6 +51,150 @@ pub enum Gain {
     X60 = 3,    
 }
 
+impl Gain {
+    pub fn factor(self) -> f32 {
+        match self {
+            Gain::X1  => 1.0,
+            Gain::X4  => 4.0,
+            Gain::X16 => 16.0,
+            Gain::X60 => 60.0,
+        }
+    }
+}
+
+// Consecutive out of range cycles before the interrupt fires
+#[derive(Copy, Clone)]
+pub enum Persistence {
+    Every    = 0,
+    Cycles1  = 1,
+    Cycles2  = 2,
+    Cycles3  = 3,
+    Cycles5  = 4,
+    Cycles10 = 5,
+    Cycles15 = 6,
+    Cycles20 = 7,
+    Cycles25 = 8,
+    Cycles30 = 9,
+    Cycles35 = 10,
+    Cycles40 = 11,
+    Cycles45 = 12,
+    Cycles50 = 13,
+    Cycles55 = 14,
+    Cycles60 = 15,
+}
+
+// Lights switching on or off, seen by the clear channel interrupt
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightEvent {
+    On,
+    Off,
+}
+
+// Clear counts above `on` mean the lights are on, below `off` that they are
+// off, in between keeps the previous state. Counts are at the gain and
+// integration time set when watching starts.
+#[derive(Copy, Clone)]
+pub struct Watch {
+    pub off  : u16,
+    pub on   : u16,
+    pub pers : Persistence,
+}
+
+// Wait between two RGBC cycles : WTIME cycles of 2.4 ms, 12 times longer
+// with WLONG
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WaitTime {
+    wtime : u8,
+    wlong : bool,
+}
+
+impl WaitTime {
+    // Closest wait to `ms`, from 2.4 ms to 7372.8 ms
+    pub fn from_ms(ms : f32) -> WaitTime {
+        if ms <= 256.0 * 2.4 {
+            WaitTime { wtime : time_reg_conv(ms), wlong : false }
+        } else {
+            WaitTime { wtime : time_reg_conv(ms / 12.0), wlong : true }
+        }
+    }
+
+    pub fn duration(self) -> Duration {
+        let us = atime_us(self.wtime);
+        Duration::from_micros(if self.wlong { us * 12 } else { us })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightMode {
+    // RGBC runs for each read only
+    OneShot,
+    // RGBC runs all the time, a read gets the latest sample
+    Continuous,
+    // Continuous, sleeping between cycles
+    LowPower(WaitTime),
+}
+
+#[derive(Copy, Clone)]
+pub struct LightConf {
+    pub gain           : Gain,
+    pub integration_ms : f32,
+    pub mode           : LightMode,
+    // Starts from gain and integration_ms
+    pub auto_range     : bool,
+    pub watch          : Option<Watch>,
+}
+
+impl Default for LightConf {
+    fn default() -> LightConf {
+        LightConf {
+            gain           : Gain::X16,
+            integration_ms : 30.0,
+            mode           : LightMode::OneShot,
+            auto_range     : false,
+            watch          : None,
+        }
+    }
+}
+
+impl LightConf {
+    pub fn gain(mut self, gain : Gain) -> LightConf {
+        self.gain = gain;
+        self
+    }
+
+    pub fn integration_ms(mut self, ms : f32) -> LightConf {
+        self.integration_ms = ms;
+        self
+    }
+
+    pub fn mode(mut self, mode : LightMode) -> LightConf {
+        self.mode = mode;
+        self
+    }
+
+    pub fn auto_range(mut self, on : bool) -> LightConf {
+        self.auto_range = on;
+        self
+    }
+
+    pub fn watch(mut self, watch : Watch) -> LightConf {
+        self.watch = Some(watch);
+        self
+    }
+
+    pub fn atime(&self) -> u8 {
+        time_reg_conv(self.integration_ms)
+    }
+
+    // WTIME and CONFIG register values
+    pub fn wait_regs(&self) -> (u8, u8) {
+        match self.mode {
+            LightMode::LowPower(w) => (w.wtime, if w.wlong { LIGHT_CFG_MSK_WLONG_EN } else { 0 }),
+            _ => (0xFF, 0),
+        }
+    }
+}
+
 pub enum Color {
     Clear,
     Red, 
@@ -56,69 +203,440 @@ pub enum Color {
 }
 
 const LIGHT_ID_VAL : u8 = 0x44;
+pub const LIGHT_ID_STR : &str = "tcs34725";
 
 const LIGHT_STATUS_MSK_CLR_CH_INT : u8 = 1 << 4;
 const LIGHT_STATUS_MSK_MEAS_VALID : u8 = 1 << 0;
 
+// ATIME / WTIME value of a time in ms, 2.4 ms per cycle
 fn time_reg_conv(ms : f32) -> u8 {
-    let ms = if ms > 700.0 {
-        700.0
-    } else if ms < 2.4 {
-        2.4
-    } else {
-        ms
-    };
+    let cycles = (ms / 2.4).round().clamp(1.0, 256.0) as u16;
+
+    (256 - cycles) as u8
+}
+
+// Power on to first RGBC cycle
+const LIGHT_INIT_TIME : Duration = Duration::from_micros(2400);
 
-    (256 - std::cmp::min(256, ms.ceil() as u16)) as u8
+// Integration time in µs of an ATIME value, 2.4 ms per cycle
+fn atime_us(atime : u8) -> u64 {
+    (256 - atime as u64) * 2400
 }
 
-pub struct Light {
+// Lux and CCT coefficients of the TAOS DN40 design note, open air
+const DN40_GA        : f32 = 1.0;
+const DN40_DF        : f32 = 310.0;
+const DN40_R_COEF    : f32 = 0.136;
+const DN40_G_COEF    : f32 = 1.0;
+const DN40_B_COEF    : f32 = -0.444;
+const DN40_CT_COEF   : f32 = 3810.0;
+const DN40_CT_OFFSET : f32 = 1391.0;
 
+// IR content of a reading, removed from every channel
+fn ir(c : u32, r : u32, g : u32, b : u32) -> f32 {
+    ((r + g + b) as f32 - c as f32).max(0.0) / 2.0
 }
 
-impl Light {
-    pub fn new() -> Light {
-        let mut i2c = LinuxI2CDevice::new("/dev/i2c-1", LIGHT_I2C_ADDR).unwrap();
-        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable)  as u8, LIGHT_ENA_MSK_PWR_EN).unwrap();
-        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Atime)   as u8, time_reg_conv(30.0)).unwrap();
-        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Wtime)   as u8, time_reg_conv(30.0)).unwrap();
-        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Control) as u8, Gain::X16 as u8).unwrap();
+// Illuminance in lux of a reading taken with `atime` and `gain`
+pub fn lux(c : u32, r : u32, g : u32, b : u32, atime : u8, gain : Gain) -> f32 {
+    let ir = ir(c, r, g, b);
+    let g2 = DN40_R_COEF * (r as f32 - ir) + DN40_G_COEF * (g as f32 - ir) + DN40_B_COEF * (b as f32 - ir);
+    // Counts per lux
+    let cpl = (atime_us(atime) as f32 / 1000.0) * gain.factor() / (DN40_GA * DN40_DF);
+    (g2 / cpl).max(0.0)
+}
 
-        let id = i2c.smbus_read_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Id as u8)).unwrap();
-        if id != LIGHT_ID_VAL { panic!(format!("Problem while reading ID of light sensor. ID (=0x{:02X}) != 0x{:02X}", id, LIGHT_ID_VAL)) }
-        Light{}
+// Correlated colour temperature in kelvin, none without red light
+pub fn cct(c : u32, r : u32, g : u32, b : u32) -> Option<f32> {
+    let ir = ir(c, r, g, b);
+    let (r2, b2) = (r as f32 - ir, b as f32 - ir);
+    if r2 <= 0.0 {
+        return None;
     }
+    Some(DN40_CT_COEF * b2.max(0.0) / r2 + DN40_CT_OFFSET)
+}
+
+// Clear count at which a reading saturates : 1024 counts per cycle up to
+// 65535, and the analog stage saturates at 75 % of it below 64 cycles
+pub fn saturation(atime : u8) -> u32 {
+    let cycles = 256 - atime as u32;
+    let max = std::cmp::min(65535, cycles * 1024);
+    if cycles < 64 { max * 3 / 4 } else { max }
+}
+
+// Auto-range steps, most sensitive first : gain and integration cycles,
+// each step is 4 times less sensitive than the previous one
+const RANGES : [(Gain, u16); 7] = [
+    (Gain::X60, 256),
+    (Gain::X60, 64),
+    (Gain::X16, 64),
+    (Gain::X4,  64),
+    (Gain::X1,  64),
+    (Gain::X1,  16),
+    (Gain::X1,  4),
+];
 
-    pub fn get_all(&self) -> (u32, u32, u32, u32) {
-        let mut i2c = LinuxI2CDevice::new("/dev/i2c-1", LIGHT_I2C_ADDR).unwrap();
-        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable) as u8, LIGHT_ENA_MSK_PWR_EN | LIGHT_ENA_MSK_RGBC_EN).unwrap();
-        let status = i2c.smbus_read_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Status) as u8).unwrap();
-        loop {
-            thread::sleep(Duration::new(0, 10000000));
-            let status = i2c.smbus_read_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Status) as u8).unwrap();
-            if status & LIGHT_STATUS_MSK_MEAS_VALID != 0 {
-                break;
+fn sensitivity(gain : Gain, cycles : u16) -> f32 {
+    gain.factor() * cycles as f32
+}
+
+// Steps down once the clear channel is above 90 % of the saturation, up
+// when the next step would stay below 70 % of its own
+const RANGE_HIGH : f32 = 0.9;
+const RANGE_LOW  : f32 = 0.7;
+
+pub struct Reading {
+    pub clear     : u32,
+    pub red       : u32,
+    pub green     : u32,
+    pub blue      : u32,
+    pub gain      : Gain,
+    pub atime     : u8,
+    pub saturated : bool,
+}
+
+impl Reading {
+    pub fn lux(&self) -> f32 {
+        lux(self.clear, self.red, self.green, self.blue, self.atime, self.gain)
+    }
+
+    pub fn cct(&self) -> Option<f32> {
+        cct(self.clear, self.red, self.green, self.blue)
+    }
+
+    pub fn integration_ms(&self) -> f32 {
+        atime_us(self.atime) as f32 / 1000.0
+    }
+}
+
+pub struct Light<D : I2CDevice> {
+    i2c   : Device<D>,
+    atime : u8,
+    gain  : Gain,
+    mode  : LightMode,
+    // Current step of RANGES when auto-ranging
+    range : Option<usize>,
+    // Watched levels and whether the lights are on
+    watch : Option<(Watch, bool)>,
+    // Settings changed while RGBC is running : time until a cycle made
+    // with the new settings is over
+    settle : Option<Duration>,
+}
+
+impl<D : I2CDevice> Light<D> {
+    pub fn new(i2c : D, conf : LightConf) -> Result<Light<D>> {
+        let mut i2c = Device::new(LIGHT_ID_STR, i2c);
+        i2c.check_id(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Id as u8), LIGHT_ID_VAL)?;
+
+        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable)  as u8, LIGHT_ENA_MSK_PWR_EN)?;
+        let atime = conf.atime();
+        let (wtime, config) = conf.wait_regs();
+        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Atime)   as u8, atime)?;
+        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Wtime)   as u8, wtime)?;
+        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Config)  as u8, config)?;
+        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Control) as u8, conf.gain as u8)?;
+        let mut light = Light{ i2c, atime, gain : conf.gain, mode : conf.mode, range : None, watch : None, settle : None };
+        light.set_auto_range(conf.auto_range)?;
+        light.write_enable()?;
+        if let Some(w) = conf.watch {
+            light.watch(w)?;
+        }
+        Ok(light)
+    }
+
+    fn write_enable(&mut self) -> Result<()> {
+        let enable = self.enable_bits();
+        self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable) as u8, enable)
+    }
+
+    pub fn set_mode(&mut self, mode : LightMode) -> Result<()> {
+        let (wtime, config) = LightConf { mode, ..LightConf::default() }.wait_regs();
+        self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Wtime)  as u8, wtime)?;
+        self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Config) as u8, config)?;
+        self.mode = mode;
+        self.write_enable()
+    }
+
+    // Fixed gain and integration time, turns auto-ranging off
+    pub fn set_range(&mut self, gain : Gain, integration_ms : f32) -> Result<()> {
+        self.range = None;
+        self.write_range(gain, time_reg_conv(integration_ms))
+    }
+
+    fn write_range(&mut self, gain : Gain, atime : u8) -> Result<()> {
+        self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Atime)   as u8, atime)?;
+        self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Control) as u8, gain as u8)?;
+        let old = self.cycle();
+        self.atime = atime;
+        self.gain = gain;
+        if self.running() {
+            self.settle = Some(old + self.cycle());
+        }
+        Ok(())
+    }
+
+    fn cycle(&self) -> Duration {
+        let wait = match self.mode {
+            LightMode::LowPower(w) => w.duration(),
+            _ => Duration::from_micros(0),
+        };
+        LIGHT_INIT_TIME + Duration::from_micros(atime_us(self.atime)) + wait
+    }
+
+    // RGBC keeps running between reads in continuous mode and while the
+    // interrupt is used
+    fn running(&self) -> bool {
+        self.mode != LightMode::OneShot || self.watch.is_some()
+    }
+
+    fn enable_bits(&self) -> u8 {
+        let mut enable = LIGHT_ENA_MSK_PWR_EN;
+        if self.running() {
+            enable |= LIGHT_ENA_MSK_RGBC_EN;
+        }
+        if let LightMode::LowPower(_) = self.mode {
+            enable |= LIGHT_ENA_MSK_WEN;
+        }
+        if self.watch.is_some() {
+            enable |= LIGHT_ENA_MSK_IRQ_EN;
+        }
+        enable
+    }
+
+    // Interrupt when the clear count goes below `low` or above `high`
+    pub fn set_irq_thresholds(&mut self, low : u16, high : u16) -> Result<()> {
+        let (low, high) = (low.to_le_bytes(), high.to_le_bytes());
+        self.i2c.smbus_write_i2c_block_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_AUTO_INC | (Registers::Ailtl) as u8,
+            &[low[0], low[1], high[0], high[1]])
+    }
+
+    pub fn set_persistence(&mut self, pers : Persistence) -> Result<()> {
+        self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Pers) as u8, pers as u8)
+    }
+
+    pub fn irq_pending(&mut self) -> Result<bool> {
+        let status = self.i2c.smbus_read_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Status) as u8)?;
+        Ok(status & LIGHT_STATUS_MSK_CLR_CH_INT != 0)
+    }
+
+    pub fn clear_irq(&mut self) -> Result<()> {
+        self.i2c.smbus_write_byte(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_SPEC_FN | LIGHT_SPEC_FN_CLEAR_IRQ)
+    }
+
+    // Starts watching for the lights switching on or off. RGBC then runs
+    // continuously, even in one-shot mode, and the chip latches the switch
+    // until `poll_event`.
+    pub fn watch(&mut self, watch : Watch) -> Result<()> {
+        let lit = self.get_all()?.0 >= watch.on as u32;
+        self.watch = Some((watch, lit));
+        self.set_persistence(watch.pers)?;
+        self.arm()?;
+        self.clear_irq()?;
+        self.write_enable()
+    }
+
+    pub fn unwatch(&mut self) -> Result<()> {
+        self.watch = None;
+        self.write_enable()?;
+        self.clear_irq()
+    }
+
+    // Only the switch away from the current state raises the interrupt
+    fn arm(&mut self) -> Result<()> {
+        match self.watch {
+            Some((w, true))  => self.set_irq_thresholds(w.off, u16::MAX),
+            Some((w, false)) => self.set_irq_thresholds(0, w.on),
+            None => Ok(()),
+        }
+    }
+
+    // Switch latched since the previous call, if any
+    pub fn poll_event(&mut self) -> Result<Option<LightEvent>> {
+        let lit = match self.watch {
+            Some((_, lit)) => lit,
+            None => return Ok(None),
+        };
+        if !self.irq_pending()? {
+            return Ok(None);
+        }
+        if let Some((_, ref mut state)) = self.watch {
+            *state = !lit;
+        }
+        self.arm()?;
+        self.clear_irq()?;
+        Ok(Some(if lit { LightEvent::Off } else { LightEvent::On }))
+    }
+
+    pub fn lights_on(&self) -> Option<bool> {
+        self.watch.map(|(_, lit)| lit)
+    }
+
+    // Starts from the step closest to the current gain and integration time
+    pub fn set_auto_range(&mut self, on : bool) -> Result<()> {
+        if !on {
+            self.range = None;
+            return Ok(());
+        }
+        let cur = sensitivity(self.gain, 256 - self.atime as u16);
+        let step = (0..RANGES.len())
+            .min_by(|&a, &b| {
+                let dist = |i : usize| (sensitivity(RANGES[i].0, RANGES[i].1) / cur).ln().abs();
+                dist(a).partial_cmp(&dist(b)).unwrap()
+            })
+            .unwrap_or(0);
+        self.set_step(step)
+    }
+
+    fn set_step(&mut self, step : usize) -> Result<()> {
+        let (gain, cycles) = RANGES[step];
+        self.write_range(gain, (256 - cycles) as u8)?;
+        self.range = Some(step);
+        Ok(())
+    }
+
+    pub fn gain(&self) -> Gain {
+        self.gain
+    }
+
+    pub fn integration_ms(&self) -> f32 {
+        atime_us(self.atime) as f32 / 1000.0
+    }
+
+    // Next auto-range step for a clear count read at the current step
+    fn next_step(&self, step : usize, clear : u32) -> Option<usize> {
+        let sat = saturation(self.atime) as f32;
+        if clear as f32 >= RANGE_HIGH * sat {
+            return if step + 1 < RANGES.len() { Some(step + 1) } else { None };
+        }
+        if step == 0 {
+            return None;
+        }
+        let (gain, cycles) = RANGES[step - 1];
+        let (cur_gain, cur_cycles) = RANGES[step];
+        let expected = clear as f32 * sensitivity(gain, cycles) / sensitivity(cur_gain, cur_cycles);
+        if expected < RANGE_LOW * saturation((256 - cycles) as u8) as f32 {
+            Some(step - 1)
+        } else {
+            None
+        }
+    }
+
+    // Reads all channels. When auto-ranging, reads again at the next step
+    // until the clear channel is in range, at most once per step.
+    pub fn read(&mut self) -> Result<Reading> {
+        let mut rd = self.read_once()?;
+        if let Some(mut step) = self.range {
+            for _ in 0..RANGES.len() {
+                step = match self.next_step(step, rd.clear) {
+                    Some(next) => next,
+                    None => break,
+                };
+                self.set_step(step)?;
+                rd = self.read_once()?;
             }
         }
+        Ok(rd)
+    }
 
-        let values = i2c.smbus_read_i2c_block_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_AUTO_INC | (Registers::Cdatal) as u8, 8).unwrap();
-        i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable) as u8, LIGHT_ENA_MSK_PWR_EN).unwrap();
-        (
-            ((values[0] as u32) << 8) + (values[1] as u32), 
-            ((values[2] as u32) << 8) + (values[3] as u32), 
-            ((values[4] as u32) << 8) + (values[5] as u32), 
-            ((values[6] as u32) << 8) + (values[7] as u32)
-        )
+    fn read_once(&mut self) -> Result<Reading> {
+        let (clear, red, green, blue) = self.get_all()?;
+        Ok(Reading {
+            clear, red, green, blue,
+            gain      : self.gain,
+            atime     : self.atime,
+            saturated : clear >= saturation(self.atime),
+        })
     }
 
-    pub fn get(&self, color : Color) -> u32 {
-        let all = self.get_all();
-        match color {
+    pub fn get_all(&mut self) -> Result<(u32, u32, u32, u32)> {
+        let cycle = self.cycle();
+        if self.running() {
+            // The cycle in progress may have started with the previous settings
+            if let Some(settle) = self.settle.take() {
+                thread::sleep(settle);
+            }
+        } else {
+            self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable) as u8, LIGHT_ENA_MSK_PWR_EN | LIGHT_ENA_MSK_RGBC_EN)?;
+        }
+        let status_reg = LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Status) as u8;
+        self.i2c.poll(status_reg, cycle, |d| {
+            Ok(d.smbus_read_byte_data(status_reg)? & LIGHT_STATUS_MSK_MEAS_VALID != 0)
+        })?;
+
+        let values = self.i2c.smbus_read_i2c_block_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_AUTO_INC | (Registers::Cdatal) as u8, 8)?;
+        if !self.running() {
+            self.i2c.smbus_write_byte_data(LIGHT_REG_CMD_MSK | LIGHT_CMD_PRTCL_BYTE | (Registers::Enable) as u8, LIGHT_ENA_MSK_PWR_EN)?;
+        }
+        // Low byte first
+        Ok((
+            (values[0] as u32) + ((values[1] as u32) << 8),
+            (values[2] as u32) + ((values[3] as u32) << 8),
+            (values[4] as u32) + ((values[5] as u32) << 8),
+            (values[6] as u32) + ((values[7] as u32) << 8)
+        ))
+    }
+
+    // Illuminance in lux and colour temperature in kelvin
+    pub fn get_lux_cct(&mut self) -> Result<(f32, Option<f32>)> {
+        let rd = self.read()?;
+        Ok((rd.lux(), rd.cct()))
+    }
+
+    pub fn get(&mut self, color : Color) -> Result<u32> {
+        let all = self.get_all()?;
+        Ok(match color {
             Color::Clear => all.0,
             Color::Red   => all.1,
             Color::Green => all.2,
             Color::Blue  => all.3,
-        }
+        })
     }
 }
 
+pub const LIGHT_QUANTITIES : [Quantity; 11] = [
+    Quantity::LightClear, Quantity::LightRed, Quantity::LightGreen, Quantity::LightBlue,
+    Quantity::Illuminance, Quantity::ColorTemp,
+    Quantity::LightGain, Quantity::LightIntegration, Quantity::LightSaturated,
+    Quantity::LightOn, Quantity::LightSwitch,
+];
+
+impl<D : I2CDevice + Send> Sensor for Light<D> {
+    fn id(&self) -> &str {
+        LIGHT_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &LIGHT_QUANTITIES
+    }
+
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let rd = self.read()?;
+        let mut ms = vec![
+            Measurement::new(self.id(), Quantity::LightClear,  Unit::Counts, rd.clear),
+            Measurement::new(self.id(), Quantity::LightRed,    Unit::Counts, rd.red),
+            Measurement::new(self.id(), Quantity::LightGreen,  Unit::Counts, rd.green),
+            Measurement::new(self.id(), Quantity::LightBlue,   Unit::Counts, rd.blue),
+            Measurement::new(self.id(), Quantity::Illuminance, Unit::Lux,    rd.lux()),
+        ];
+        if let Some(k) = rd.cct() {
+            ms.push(Measurement::new(self.id(), Quantity::ColorTemp, Unit::Kelvin, k));
+        }
+        // Range the values were read with
+        ms.push(Measurement::new(self.id(), Quantity::LightGain,        Unit::Ratio,       rd.gain.factor()));
+        ms.push(Measurement::new(self.id(), Quantity::LightIntegration, Unit::Millisecond, rd.integration_ms()));
+        ms.push(Measurement::new(self.id(), Quantity::LightSaturated,   Unit::Code,        rd.saturated as u8));
+        // Switch latched by the chip since the previous read, so a short
+        // one is not missed between two samples. It is reported, stamped
+        // with the time it was seen, on top of the state it left.
+        let event = self.poll_event()?;
+        if let Some(on) = self.lights_on() {
+            ms.push(Measurement::new(self.id(), Quantity::LightOn, Unit::Code, on as u8));
+        }
+        match event {
+            Some(LightEvent::On)  => ms.push(Measurement::new(self.id(), Quantity::LightSwitch, Unit::Code, 1)),
+            Some(LightEvent::Off) => ms.push(Measurement::new(self.id(), Quantity::LightSwitch, Unit::Code, -1)),
+            None => (),
+        }
+        Ok(ms)
+    }
+}
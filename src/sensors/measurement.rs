@@ -0,0 +1,232 @@
+use chrono::prelude::*;
+use serde_json::{Map, Value};
+use std::fmt;
+
+use crate::error::Result;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    SeaLevelPressure,
+    Altitude,
+    Humidity,
+    LightClear,
+    LightRed,
+    LightGreen,
+    LightBlue,
+    Illuminance,
+    ColorTemp,
+    LightGain,
+    LightIntegration,
+    LightSaturated,
+    LightOn,
+    LightSwitch,
+    AirQuality,
+    AdcAn0,
+    AdcAn1,
+    AdcAn2,
+    AdcAn3,
+    AdcAn0An1,
+    AdcAn0An3,
+    AdcAn1An3,
+    AdcAn2An3,
+    AdcAlert,
+    AccelX,
+    AccelY,
+    AccelZ,
+    GyroX,
+    GyroY,
+    GyroZ,
+    MagX,
+    MagY,
+    MagZ,
+    BaroTrend,
+    BaroTendency,
+    Forecast,
+    DewPoint,
+    AbsHumidity,
+    HeatIndex,
+    Humidex,
+    MouldRisk,
+}
+
+impl Quantity {
+    // Telemetry key, kept identical to the historical payload field names
+    pub fn key(self) -> &'static str {
+        match self {
+            Quantity::Temperature      => "temperature",
+            Quantity::Pressure         => "pressure",
+            Quantity::SeaLevelPressure => "pressure_sea_level",
+            Quantity::Altitude         => "altitude",
+            Quantity::Humidity         => "humidity",
+            Quantity::LightClear       => "light_clear",
+            Quantity::LightRed         => "light_red",
+            Quantity::LightGreen       => "light_green",
+            Quantity::LightBlue        => "light_blue",
+            Quantity::Illuminance      => "illuminance",
+            Quantity::ColorTemp        => "color_temp",
+            Quantity::LightGain        => "light_gain",
+            Quantity::LightIntegration => "light_integration",
+            Quantity::LightSaturated   => "light_saturated",
+            Quantity::LightOn          => "light_on",
+            Quantity::LightSwitch      => "light_switch",
+            Quantity::AirQuality       => "airquality",
+            Quantity::AdcAn0           => "adc_an0",
+            Quantity::AdcAn1           => "adc_an1",
+            Quantity::AdcAn2           => "adc_an2",
+            Quantity::AdcAn3           => "adc_an3",
+            Quantity::AdcAn0An1        => "adc_an0_an1",
+            Quantity::AdcAn0An3        => "adc_an0_an3",
+            Quantity::AdcAn1An3        => "adc_an1_an3",
+            Quantity::AdcAn2An3        => "adc_an2_an3",
+            Quantity::AdcAlert         => "adc_alert",
+            Quantity::AccelX           => "accel_x",
+            Quantity::AccelY           => "accel_y",
+            Quantity::AccelZ           => "accel_z",
+            Quantity::GyroX            => "gyro_x",
+            Quantity::GyroY            => "gyro_y",
+            Quantity::GyroZ            => "gyro_z",
+            Quantity::MagX             => "mag_x",
+            Quantity::MagY             => "mag_y",
+            Quantity::MagZ             => "mag_z",
+            Quantity::BaroTrend        => "baro_trend",
+            Quantity::BaroTendency     => "baro_tendency",
+            Quantity::Forecast         => "forecast",
+            Quantity::DewPoint         => "dew_point",
+            Quantity::AbsHumidity      => "abs_humidity",
+            Quantity::HeatIndex        => "heat_index",
+            Quantity::Humidex          => "humidex",
+            Quantity::MouldRisk        => "mould_risk",
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Unit {
+    DegC,
+    Pascal,
+    Percent,
+    Counts,
+    Ratio,
+    Volt,
+    G,
+    Dps,
+    Gauss,
+    Meter,
+    HpaPerHour,
+    GramPerM3,
+    Lux,
+    Kelvin,
+    Millisecond,
+    // Unitless code, e.g. a forecast number
+    Code,
+}
+
+impl Unit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::DegC        => "*C",
+            Unit::Pascal      => "Pa",
+            Unit::Percent     => "%",
+            Unit::Counts      => "",
+            Unit::Ratio       => "",
+            Unit::Volt        => "V",
+            Unit::G           => "g",
+            Unit::Dps         => "dps",
+            Unit::Gauss       => "gauss",
+            Unit::Meter       => "m",
+            Unit::HpaPerHour  => "hPa/h",
+            Unit::GramPerM3   => "g/m3",
+            Unit::Lux         => "lx",
+            Unit::Kelvin      => "K",
+            Unit::Millisecond => "ms",
+            Unit::Code        => "",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub sensor    : String,
+    pub quantity  : Quantity,
+    pub unit      : Unit,
+    pub value     : f64,
+    pub timestamp : DateTime<Utc>,
+}
+
+impl Measurement {
+    pub fn new<T : Into<f64>>(sensor : &str, quantity : Quantity, unit : Unit, value : T) -> Measurement {
+        Measurement {
+            sensor : sensor.to_owned(),
+            quantity,
+            unit,
+            value : value.into(),
+            timestamp : Utc::now(),
+        }
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<12}: {}", self.quantity.key(), self.value)?;
+        match self.unit.symbol() {
+            "" => Ok(()),
+            s  => write!(f, " {}", s),
+        }
+    }
+}
+
+// Sensors are read on their own worker thread
+pub trait Sensor : Send {
+    fn id(&self) -> &str;
+
+    // Quantities `measure` can report, in the same order. Optional ones may
+    // be left out.
+    fn quantities(&self) -> &'static [Quantity];
+
+    fn measure(&mut self) -> Result<Vec<Measurement>>;
+}
+
+// Telemetry key of each measurement. The first sensor reporting a quantity
+// gets the bare key (e.g. "pressure"); any other sensor reporting the same
+// quantity gets it suffixed with its id (e.g. "pressure_lps25h").
+pub fn telemetry_keys(measurements : &[Measurement]) -> Vec<String> {
+    let mut owners : Vec<(Quantity, String)> = Vec::new();
+    for m in measurements {
+        if !owners.iter().any(|(q, _)| *q == m.quantity) {
+            owners.push((m.quantity, m.sensor.clone()));
+        }
+    }
+    measurements.iter().map(|m| telemetry_key(&owners, m)).collect()
+}
+
+// Same with the owner of each quantity given up front, so that keys do not
+// change when the owner misses a cycle
+pub fn telemetry_key(owners : &[(Quantity, String)], m : &Measurement) -> String {
+    match owners.iter().find(|(q, _)| *q == m.quantity) {
+        Some((_, s)) if *s != m.sensor => format!("{}_{}", m.quantity.key(), m.sensor),
+        _ => m.quantity.key().to_owned(),
+    }
+}
+
+// Builds a flat telemetry object keyed with `telemetry_keys`
+pub fn to_json(measurements : &[Measurement]) -> Value {
+    let mut obj = Map::new();
+    for (key, m) in telemetry_keys(measurements).into_iter().zip(measurements) {
+        obj.insert(key, Value::from(m.value));
+    }
+    Value::Object(obj)
+}
@@ -0,0 +1,182 @@
+use i2cdev::core::I2CDevice;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+// Register-map-backed stand-in for an I2C slave, used to run the drivers
+// without hardware. Clones share the same register map, so a test can keep
+// one handle while the driver owns the other.
+
+type WriteHook = Box<dyn FnMut(&mut [u8; 256]) + Send>;
+
+struct RegisterMap {
+    regs    : [u8; 256],
+    ptr     : u8,
+    reg_msk : u8,
+    offline : bool,
+    hooks   : Vec<(u8, WriteHook)>,
+    cmds    : Vec<(u8, WriteHook)>,
+}
+
+#[derive(Clone)]
+pub struct MockI2CDevice {
+    map : Arc<Mutex<RegisterMap>>,
+}
+
+impl Default for MockI2CDevice {
+    fn default() -> MockI2CDevice {
+        MockI2CDevice::new()
+    }
+}
+
+impl MockI2CDevice {
+    pub fn new() -> MockI2CDevice {
+        MockI2CDevice::with_reg_mask(0xFF)
+    }
+
+    // Only the bits of the register pointer that are set in `reg_msk` select
+    // a register. Used for devices mixing command bits into the address byte
+    // (TCS34725 command/auto-increment bits, HTS221 auto-increment bit).
+    pub fn with_reg_mask(reg_msk : u8) -> MockI2CDevice {
+        MockI2CDevice {
+            map : Arc::new(Mutex::new(RegisterMap {
+                regs    : [0; 256],
+                ptr     : 0,
+                reg_msk,
+                offline : false,
+                hooks   : Vec::new(),
+                cmds    : Vec::new(),
+            })),
+        }
+    }
+
+    pub fn set_reg(&self, reg : u8, val : u8) {
+        self.set_regs(reg, &[val]);
+    }
+
+    pub fn set_regs(&self, reg : u8, vals : &[u8]) {
+        let mut map = self.map.lock().unwrap();
+        let reg = reg & map.reg_msk;
+        for (i, val) in vals.iter().enumerate() {
+            map.regs[reg.wrapping_add(i as u8) as usize] = *val;
+        }
+    }
+
+    pub fn reg(&self, reg : u8) -> u8 {
+        let map = self.map.lock().unwrap();
+        map.regs[(reg & map.reg_msk) as usize]
+    }
+
+    // Called with the whole register map each time the driver writes `reg`,
+    // e.g. to clear a busy bit and load a conversion result.
+    pub fn on_write<F>(&self, reg : u8, hook : F)
+        where F : FnMut(&mut [u8; 256]) + Send + 'static
+    {
+        let mut map = self.map.lock().unwrap();
+        let reg = reg & map.reg_msk;
+        map.hooks.push((reg, Box::new(hook)));
+    }
+
+    // Called each time a write starts with the unmasked byte `cmd`, e.g. a
+    // special function sent as a lone command byte.
+    pub fn on_command<F>(&self, cmd : u8, hook : F)
+        where F : FnMut(&mut [u8; 256]) + Send + 'static
+    {
+        self.map.lock().unwrap().cmds.push((cmd, Box::new(hook)));
+    }
+
+    // While offline every transfer fails, like a device missing from the bus.
+    pub fn set_offline(&self, offline : bool) {
+        self.map.lock().unwrap().offline = offline;
+    }
+}
+
+impl RegisterMap {
+    fn check_online(&self) -> io::Result<()> {
+        if self.offline {
+            Err(io::Error::new(io::ErrorKind::NotConnected, "Mock I2C device is offline"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read(&mut self, data : &mut [u8]) -> io::Result<()> {
+        self.check_online()?;
+        for byte in data.iter_mut() {
+            *byte = self.regs[self.ptr as usize];
+            self.ptr = self.ptr.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data : &[u8]) -> io::Result<()> {
+        self.check_online()?;
+        let (reg, vals) = match data.split_first() {
+            Some((reg, vals)) => (reg & self.reg_msk, vals),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty I2C write")),
+        };
+        let regs = &mut self.regs;
+        for (_, hook) in self.cmds.iter_mut().filter(|(c, _)| *c == data[0]) {
+            hook(regs);
+        }
+        self.ptr = reg;
+        for val in vals {
+            let cur = self.ptr;
+            self.regs[cur as usize] = *val;
+            let regs = &mut self.regs;
+            for (_, hook) in self.hooks.iter_mut().filter(|(r, _)| *r == cur) {
+                hook(regs);
+            }
+            self.ptr = self.ptr.wrapping_add(1);
+        }
+        Ok(())
+    }
+}
+
+impl I2CDevice for MockI2CDevice {
+    type Error = io::Error;
+
+    fn read(&mut self, data : &mut [u8]) -> io::Result<()> {
+        self.map.lock().unwrap().read(data)
+    }
+
+    fn write(&mut self, data : &[u8]) -> io::Result<()> {
+        self.map.lock().unwrap().write(data)
+    }
+
+    fn smbus_write_quick(&mut self, _bit : bool) -> io::Result<()> {
+        self.map.lock().unwrap().check_online()
+    }
+
+    fn smbus_read_block_data(&mut self, register : u8) -> io::Result<Vec<u8>> {
+        let len = self.smbus_read_byte_data(register)?;
+        let mut data = vec![0; len.min(32) as usize];
+        self.read(&mut data)?;
+        Ok(data)
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register : u8, len : u8) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; len.min(32) as usize];
+        self.write(&[register])?;
+        self.read(&mut data)?;
+        Ok(data)
+    }
+
+    fn smbus_write_block_data(&mut self, register : u8, values : &[u8]) -> io::Result<()> {
+        let mut data = vec![register, values.len() as u8];
+        data.extend_from_slice(values);
+        self.write(&data)
+    }
+
+    fn smbus_write_i2c_block_data(&mut self, register : u8, values : &[u8]) -> io::Result<()> {
+        let mut data = vec![register];
+        data.extend_from_slice(values);
+        self.write(&data)
+    }
+
+    fn smbus_process_block(&mut self, register : u8, values : &[u8]) -> io::Result<Vec<u8>> {
+        self.smbus_write_i2c_block_data(register, values)?;
+        let mut data = vec![0; values.len()];
+        self.read(&mut data)?;
+        Ok(data)
+    }
+}
@@ -1,4 +1,12 @@
+pub mod ads1115;
 pub mod airq;
+pub mod bus;
+pub mod device;
 pub mod press_temp;
 pub mod light;
-pub mod humidity;
\ No newline at end of file
+pub mod humidity;
+pub mod imu;
+pub mod measurement;
+pub mod mock;
+
+pub use self::measurement::{Measurement, Quantity, Sensor, Unit};
\ No newline at end of file
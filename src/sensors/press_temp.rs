@@ -1,6 +1,10 @@
 use i2cdev::core::*;
-use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+use crate::error::{Error, Result};
+use crate::sensors::device::Device;
+use crate::sensors::measurement::{Measurement, Quantity, Sensor, Unit};
+use std::time::Duration;
 
+#[allow(dead_code, clippy::enum_variant_names)]
 enum Reg {
     FirstCoeffReg = 0xAA,
     DataOutMsb = 0xF6,
@@ -17,6 +21,21 @@ pub enum Oss {
     Oss8,
 }
 
+impl Oss {
+    // Maximum pressure conversion time given by the datasheet
+    pub fn conv_time(self) -> Duration {
+        match self {
+            Oss::Oss1 => Duration::from_micros(4500),
+            Oss::Oss2 => Duration::from_micros(7500),
+            Oss::Oss4 => Duration::from_micros(13500),
+            Oss::Oss8 => Duration::from_micros(25500),
+        }
+    }
+}
+
+// Maximum temperature conversion time
+const TEMP_CONV_TIME : Duration = Duration::from_micros(4500);
+
 enum CtrlMeasureCmd {
     Temperature = 0x2E,
     PressureSingle = 0x34,
@@ -25,7 +44,49 @@ enum CtrlMeasureCmd {
     PressureOss8 = 0xF4,
 }
 
-pub struct BMP180 {
+// International standard atmosphere, as given in the BMP180 datasheet.
+// Valid for both sensors.
+const ISA_ALTITUDE_M : f32 = 44330.0;
+const ISA_EXPONENT   : f32 = 5.255;
+
+// Station pressure `p` (Pa) reduced to sea level for a station at
+// `altitude` m
+pub fn sea_level_pressure(p : f32, altitude : f32) -> f32 {
+    p / (1.0 - altitude / ISA_ALTITUDE_M).powf(ISA_EXPONENT)
+}
+
+// Altitude in m at which the pressure is `p` (Pa), given the current sea
+// level pressure `qnh` (Pa)
+pub fn altitude(p : f32, qnh : f32) -> f32 {
+    ISA_ALTITUDE_M * (1.0 - (p / qnh).powf(1.0 / ISA_EXPONENT))
+}
+
+// Derived values reported along with the pressure : the sea-level pressure
+// if the station altitude (m) is known, the altitude if a reference sea level
+// pressure (QNH, Pa) is given
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PressureRef {
+    pub station_altitude : Option<f32>,
+    pub qnh              : Option<f32>,
+}
+
+impl PressureRef {
+    fn measurements(&self, sensor : &str, p : f32) -> Vec<Measurement> {
+        let mut m = Vec::new();
+        if let Some(alt) = self.station_altitude {
+            m.push(Measurement::new(sensor, Quantity::SeaLevelPressure, Unit::Pascal, sea_level_pressure(p, alt)));
+        }
+        if let Some(qnh) = self.qnh {
+            m.push(Measurement::new(sensor, Quantity::Altitude, Unit::Meter, altitude(p, qnh)));
+        }
+        m
+    }
+}
+
+pub struct BMP180<D : I2CDevice> {
+    i2c: Device<D>,
+    oss: Oss,
+    pref: PressureRef,
     ac1: i16,
     ac2: i16,
     ac3: i16,
@@ -39,8 +100,10 @@ pub struct BMP180 {
     md: i16,
 }
 
-const ADDR            : u16 = 0x77;
+pub const ADDR        : u16 = 0x77;
+pub const BMP180_ID_STR   : &str = "bmp180";
 const ID              : u8  = 0x55;
+#[allow(dead_code)]
 const RESET_ORDER     : u8  = 0xB6;
 const MEAS_READY_MASK : u8  = 0x20;
 
@@ -52,14 +115,16 @@ fn to_u16(msb: u8, lsb: u8) -> u16 {
     ((msb as u16) << 8) | lsb as u16
 }
 
-impl BMP180 {
-    pub fn new() -> Result<BMP180, LinuxI2CError> {
-        let mut tmpi2c = LinuxI2CDevice::new("/dev/i2c-1", ADDR).unwrap();
-
-        let compensation_coeffs = tmpi2c.smbus_read_i2c_block_data(Reg::FirstCoeffReg as u8, 22)?;
+impl<D : I2CDevice> BMP180<D> {
+    pub fn new(i2c: D) -> Result<BMP180<D>> {
+        let mut i2c = Device::new(BMP180_ID_STR, i2c);
+        let compensation_coeffs = i2c.smbus_read_i2c_block_data(Reg::FirstCoeffReg as u8, 22)?;
 
         Ok(
             BMP180 {
+                i2c,
+                oss: Oss::Oss1,
+                pref: PressureRef::default(),
                 ac1: to_i16(compensation_coeffs[ 0], compensation_coeffs[ 1]),
                 ac2: to_i16(compensation_coeffs[ 2], compensation_coeffs[ 3]),
                 ac3: to_i16(compensation_coeffs[ 4], compensation_coeffs[ 5]),
@@ -75,17 +140,31 @@ impl BMP180 {
         )
     }
 
-    pub fn self_test(&mut self) -> Result<(), ()> {
-        let mut tmpi2c = LinuxI2CDevice::new("/dev/i2c-1", ADDR).unwrap();
-        let tmp_id = match tmpi2c.smbus_read_byte_data(Reg::ID as u8) {
-            Ok(d) => d,
-            Err(_) => return Err(()),
-        };
-        if tmp_id != ID {
-            return Err(());
-        } else {
-            return Ok(());
+    // Oversampling used when the sensor is sampled through `Sensor::measure`
+    pub fn set_oss(&mut self, oss: Oss) {
+        self.oss = oss;
+    }
+
+    // Derived values reported by `Sensor::measure`
+    pub fn set_pressure_ref(&mut self, pref: PressureRef) {
+        self.pref = pref;
+    }
+
+    // Checks the chip ID and the calibration coefficients : a blank or
+    // unreadable EEPROM reads as all 0x0000 or 0xFFFF words.
+    pub fn self_test(&mut self) -> Result<()> {
+        self.i2c.check_id(Reg::ID as u8, ID)?;
+        let words = [
+            self.ac1 as u16, self.ac2 as u16, self.ac3 as u16, self.ac4, self.ac5, self.ac6,
+            self.b1 as u16, self.b2 as u16, self.mb as u16, self.mc as u16, self.md as u16,
+        ];
+        if let Some(i) = words.iter().position(|w| *w == 0 || *w == 0xFFFF) {
+            return Err(Error::Calibration {
+                sensor : BMP180_ID_STR,
+                msg    : format!("coefficient {} (register 0x{:02X}) is 0x{:04X}", i, Reg::FirstCoeffReg as u8 + 2 * i as u8, words[i]),
+            });
         }
+        Ok(())
     }
 
     pub fn print_comp_coeffs(&self) {
@@ -102,20 +181,34 @@ impl BMP180 {
         println!("md = {}", self.md);
     }
 
-    fn read_raw_temperature(&mut self) -> Result<i32, LinuxI2CError> {
-        let mut tmpi2c = LinuxI2CDevice::new("/dev/i2c-1", ADDR)?;
-        tmpi2c.smbus_write_byte_data(Reg::CtrlMeas as u8, CtrlMeasureCmd::Temperature as u8)?;
-        let mut data_ready = false;
-        while data_ready == false {
-            data_ready = (tmpi2c.smbus_read_byte_data(Reg::CtrlMeas as u8)? & MEAS_READY_MASK) == 0;
+    fn calibration_error(&self, msg : &str) -> Error {
+        Error::Calibration { sensor : BMP180_ID_STR, msg : msg.to_owned() }
+    }
+
+    // Temperature compensation term shared by both conversions
+    fn b5(&self, ut : i32) -> Result<i32> {
+        let x1 = (((ut - self.ac6 as i32) as i64 * self.ac5 as i64) >> 15) as i32;
+        let div = x1 + self.md as i32;
+        if div == 0 {
+            return Err(self.calibration_error("X1 + MD is 0"));
         }
+        let x2 = ((self.mc as i32) << 11) / div;
+        Ok(x1 + x2)
+    }
+
+    fn read_raw_temperature(&mut self) -> Result<i32> {
+        let tmpi2c = &mut self.i2c;
+        tmpi2c.smbus_write_byte_data(Reg::CtrlMeas as u8, CtrlMeasureCmd::Temperature as u8)?;
+        tmpi2c.poll(Reg::CtrlMeas as u8, TEMP_CONV_TIME, |d| {
+            Ok(d.smbus_read_byte_data(Reg::CtrlMeas as u8)? & MEAS_READY_MASK == 0)
+        })?;
         let raw_data = tmpi2c.smbus_read_i2c_block_data(Reg::DataOutMsb as u8, 3)?;
         let raw_temp = ((raw_data[0] as i32) << 8) | (raw_data[1] as i32);
         Ok(raw_temp)
     }
 
-    fn read_raw_pressure(&mut self, oss: Oss) -> Result<i32, LinuxI2CError> {
-        let mut tmpi2c = LinuxI2CDevice::new("/dev/i2c-1", ADDR)?;
+    fn read_raw_pressure(&mut self, oss: Oss) -> Result<i32> {
+        let tmpi2c = &mut self.i2c;
         let cmd = match oss {
             Oss::Oss1 => CtrlMeasureCmd::PressureSingle,
             Oss::Oss2 => CtrlMeasureCmd::PressureOss2,
@@ -124,32 +217,35 @@ impl BMP180 {
         };
 
         tmpi2c.smbus_write_byte_data(Reg::CtrlMeas as u8, cmd as u8)?;
-        let mut data_ready = false;
-        while data_ready == false {
-            data_ready = (tmpi2c.smbus_read_byte_data(Reg::CtrlMeas as u8)?) & MEAS_READY_MASK == 0;
-        }
+        tmpi2c.poll(Reg::CtrlMeas as u8, oss.conv_time(), |d| {
+            Ok(d.smbus_read_byte_data(Reg::CtrlMeas as u8)? & MEAS_READY_MASK == 0)
+        })?;
         let raw_data = tmpi2c.smbus_read_i2c_block_data(Reg::DataOutMsb as u8, 3)?;
         let raw_pressure = (((raw_data[0] as i32) << 16) | ((raw_data[1] as i32) << 8) |
                             (raw_data[2] as i32)) >> (8 - (oss as u8));
         Ok(raw_pressure)
     }
 
-    pub fn read_pressure(&mut self, oss: Oss) -> Result<i32, LinuxI2CError> {
-        let ut = self.read_raw_temperature()?;
-        let up = self.read_raw_pressure(oss)?;
+    // Temperature in 0.1 *C of a compensation term
+    fn temperature(b5 : i32) -> i32 {
+        (b5 + 8) >> 4
+    }
 
-        let mut x1 = (ut - (self.ac6 as i32)) * (self.ac5 as i32) >> 15;
-        let mut x2 = ((self.mc as i32) << 11) / (x1 + self.md as i32);
-        let b5 = x1 + x2;
+    // Pressure in Pa of a raw reading and the compensation term of the
+    // temperature it was taken at
+    fn pressure(&self, b5 : i32, up : i32, oss : Oss) -> Result<i32> {
         let b6 = b5 - 4000;
-        x1 = ((self.b2 as i32) * ((b6 * b6) >> 12)) >> 11;
-        x2 = ((self.ac2 as i32) * b6) >> 11;
+        let mut x1 = ((self.b2 as i32) * ((b6 * b6) >> 12)) >> 11;
+        let mut x2 = ((self.ac2 as i32) * b6) >> 11;
         let mut x3 = x1 + x2;
         let b3 = ((((self.ac1 as i32) * 4 + x3) << (oss as i32)) + 2) / 4;
         x1 = ((self.ac3 as i32) * b6) >> 13;
-        x2 = ((self.b1 as i32) * ((b6.pow(2) >> 12))) >> 16;
+        x2 = ((self.b1 as i32) * (b6.pow(2) >> 12)) >> 16;
         x3 = ((x1 + x2) + 2) >> 2;
         let b4 = ((self.ac4 as u32) * ((x3 + 32768) as u32)) >> 15;
+        if b4 == 0 {
+            return Err(self.calibration_error("B4 is 0"));
+        }
         let b7 = ((up - b3) as u32) * (50000 >> (oss as u32));
         let p = if b7 < 0x80000000 {
             (b7 * 2) / b4
@@ -160,15 +256,315 @@ impl BMP180 {
         x1 = (x1 * 3038) >> 16;
         x2 = (-7357 * (p as i32)) >> 16;
         let p = (p as i32) + ((x1 + x2 + 3791) >> 4);
-        return Ok(p);
+        Ok(p)
     }
 
-    pub fn read_temperature(&mut self) -> Result<f32, LinuxI2CError> {
+    // Temperature in *C and pressure in Pa, both from the same temperature
+    // conversion
+    pub fn read(&mut self, oss: Oss) -> Result<(f32, i32)> {
         let ut = self.read_raw_temperature()?;
-        let x1 = ((ut - (self.ac6 as i32)) * (self.ac5 as i32)) >> 15;
-        let x2 = ((self.mc as i32) << 11) / (x1 + (self.md as i32));
-        let b5 = x1 + x2;
-        let t = (b5 + 8) >> 4;
-        Ok((t as f32) / 10.0)
+        let up = self.read_raw_pressure(oss)?;
+        let b5 = self.b5(ut)?;
+        Ok((Self::temperature(b5) as f32 / 10.0, self.pressure(b5, up, oss)?))
+    }
+
+    pub fn read_pressure(&mut self, oss: Oss) -> Result<i32> {
+        Ok(self.read(oss)?.1)
+    }
+
+    pub fn read_temperature(&mut self) -> Result<f32> {
+        let ut = self.read_raw_temperature()?;
+        let b5 = self.b5(ut)?;
+        Ok(Self::temperature(b5) as f32 / 10.0)
+    }
+
+    // Pressure in Pa reduced to sea level, `altitude` being the station's
+    pub fn read_sea_level_pressure(&mut self, oss: Oss, altitude: f32) -> Result<f32> {
+        Ok(sea_level_pressure(self.read_pressure(oss)? as f32, altitude))
+    }
+
+    // Altitude in m from the sea level pressure `qnh` in Pa
+    pub fn read_altitude(&mut self, oss: Oss, qnh: f32) -> Result<f32> {
+        Ok(altitude(self.read_pressure(oss)? as f32, qnh))
+    }
+}
+
+pub const LPS25H_ADDR : u16 = 0x5C;
+const LPS25H_ID       : u8  = 0xBD;
+pub const LPS25H_ID_STR   : &str = "lps25h";
+
+// The sub-address only auto-increments when its MSB is set.
+const LPS25H_AUTO_INC : u8 = 1 << 7;
+
+const LPS25H_PRESS_LSB_PER_HPA : f32 = 4096.0;
+const LPS25H_TEMP_LSB_PER_DEGC : f32 = 480.0;
+const LPS25H_TEMP_OFFSET       : f32 = 42.5;
+
+pub enum Lps25hReg {
+    RefPXl     = 0x08,
+    WhoAmI     = 0x0F,
+    ResConf    = 0x10,
+    CtrlReg1   = 0x20,
+    CtrlReg2   = 0x21,
+    Status     = 0x27,
+    PressOutXl = 0x28,
+    TempOutL   = 0x2B,
+    FifoCtrl   = 0x2E,
+    FifoStatus = 0x2F,
+    RpdsL      = 0x39,
+}
+
+pub mod lps25h_ctrl1_msks {
+    pub const PWR_UP   : u8 = 1 << 7;
+    pub const DIFF_EN  : u8 = 1 << 3;
+    pub const BDU      : u8 = 1 << 2;
+    pub const RESET_AZ : u8 = 1 << 1;
+}
+
+pub mod lps25h_ctrl2_msks {
+    pub const BOOT          : u8 = 1 << 7;
+    pub const FIFO_EN       : u8 = 1 << 6;
+    pub const FIFO_MEAN_DEC : u8 = 1 << 4;
+    pub const SWRESET       : u8 = 1 << 2;
+    pub const AUTO_ZERO     : u8 = 1 << 1;
+    pub const ONE_SHOT      : u8 = 1 << 0;
+}
+
+pub mod lps25h_status_msks {
+    pub const P_DA : u8 = 1 << 1;
+    pub const T_DA : u8 = 1 << 0;
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Lps25hOdr {
+    OneShot        = 0 << 4,
+    DataRate1Hz    = 1 << 4,
+    DataRate7Hz    = 2 << 4,
+    DataRate12p5Hz = 3 << 4,
+    DataRate25Hz   = 4 << 4,
+}
+
+impl Lps25hOdr {
+    // Time between two samples. A one-shot conversion with the largest
+    // averaging takes about as long as a sample at 1 Hz.
+    pub fn period(self) -> Duration {
+        match self {
+            Lps25hOdr::OneShot        => Duration::from_millis(1000),
+            Lps25hOdr::DataRate1Hz    => Duration::from_millis(1000),
+            Lps25hOdr::DataRate7Hz    => Duration::from_millis(143),
+            Lps25hOdr::DataRate12p5Hz => Duration::from_millis(80),
+            Lps25hOdr::DataRate25Hz   => Duration::from_millis(40),
+        }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Copy, Clone)]
+pub enum Lps25hTempAvg {
+    AvgSmplx8  = 0 << 2,
+    AvgSmplx16 = 1 << 2,
+    AvgSmplx32 = 2 << 2,
+    AvgSmplx64 = 3 << 2,
+}
+
+#[allow(clippy::identity_op)]
+#[derive(Copy, Clone)]
+pub enum Lps25hPressAvg {
+    AvgSmplx8   = 0 << 0,
+    AvgSmplx32  = 1 << 0,
+    AvgSmplx128 = 2 << 0,
+    AvgSmplx512 = 3 << 0,
+}
+
+const FIFO_MODE_BYPASS : u8 = 0 << 5;
+const FIFO_MODE_MEAN   : u8 = 6 << 5;
+
+// Number of samples of the FIFO running average, as WTM_POINT values.
+#[derive(Copy, Clone)]
+pub enum FifoMeanSmpl {
+    Mean2  = 0x01,
+    Mean4  = 0x03,
+    Mean8  = 0x07,
+    Mean16 = 0x0F,
+    Mean32 = 0x1F,
+}
+
+#[derive(Copy, Clone)]
+pub struct Lps25hConf {
+    pub odr       : Lps25hOdr,
+    pub tavg      : Lps25hTempAvg,
+    pub pavg      : Lps25hPressAvg,
+    pub fifo_mean : Option<FifoMeanSmpl>,
+}
+
+pub struct LPS25H<D : I2CDevice> {
+    i2c  : Device<D>,
+    conf : Lps25hConf,
+    pref : PressureRef,
+}
+
+impl<D : I2CDevice> LPS25H<D> {
+    pub fn new(i2c : D, conf : Lps25hConf) -> Result<LPS25H<D>> {
+        let mut i2c = Device::new(LPS25H_ID_STR, i2c);
+        i2c.check_id(Lps25hReg::WhoAmI as u8, LPS25H_ID)?;
+
+        let mut lps = LPS25H { i2c, conf, pref : PressureRef::default() };
+        lps.configure(conf)?;
+        Ok(lps)
+    }
+
+    pub fn configure(&mut self, conf : Lps25hConf) -> Result<()> {
+        // ODR and averaging may only be changed while powered down
+        self.i2c.smbus_write_byte_data(Lps25hReg::CtrlReg1 as u8, 0)?;
+        self.i2c.smbus_write_byte_data(Lps25hReg::ResConf as u8, conf.tavg as u8 | conf.pavg as u8)?;
+
+        let (fifo_ctrl, ctrl2) = match conf.fifo_mean {
+            Some(n) => (FIFO_MODE_MEAN | n as u8, lps25h_ctrl2_msks::FIFO_EN),
+            None    => (FIFO_MODE_BYPASS, 0),
+        };
+        self.i2c.smbus_write_byte_data(Lps25hReg::FifoCtrl as u8, fifo_ctrl)?;
+        self.i2c.smbus_write_byte_data(Lps25hReg::CtrlReg2 as u8, ctrl2)?;
+
+        let ctrl1 = lps25h_ctrl1_msks::PWR_UP | lps25h_ctrl1_msks::BDU | conf.odr as u8;
+        self.i2c.smbus_write_byte_data(Lps25hReg::CtrlReg1 as u8, ctrl1)?;
+
+        self.conf = conf;
+        Ok(())
+    }
+
+    fn ctrl2(&self) -> u8 {
+        match self.conf.fifo_mean {
+            Some(_) => lps25h_ctrl2_msks::FIFO_EN,
+            None    => 0,
+        }
+    }
+
+    fn wait_data(&mut self, msk : u8) -> Result<()> {
+        if self.conf.odr == Lps25hOdr::OneShot {
+            let cfg = self.ctrl2() | lps25h_ctrl2_msks::ONE_SHOT;
+            self.i2c.smbus_write_byte_data(Lps25hReg::CtrlReg2 as u8, cfg)?;
+        }
+        self.i2c.poll(Lps25hReg::Status as u8, self.conf.odr.period(), |d| {
+            Ok(d.smbus_read_byte_data(Lps25hReg::Status as u8)? & msk != 0)
+        })
+    }
+
+    // Pressure in Pa
+    pub fn read_pressure(&mut self) -> Result<f32> {
+        self.wait_data(lps25h_status_msks::P_DA)?;
+        let raw = self.i2c.smbus_read_i2c_block_data(LPS25H_AUTO_INC | Lps25hReg::PressOutXl as u8, 3)?;
+        let p = (((raw[2] as u32) << 24) | ((raw[1] as u32) << 16) | ((raw[0] as u32) << 8)) as i32 >> 8;
+        Ok(p as f32 / LPS25H_PRESS_LSB_PER_HPA * 100.0)
+    }
+
+    // Pressure in Pa reduced to sea level, `altitude` being the station's
+    pub fn read_sea_level_pressure(&mut self, altitude : f32) -> Result<f32> {
+        Ok(sea_level_pressure(self.read_pressure()?, altitude))
+    }
+
+    // Altitude in m from the sea level pressure `qnh` in Pa
+    pub fn read_altitude(&mut self, qnh : f32) -> Result<f32> {
+        Ok(altitude(self.read_pressure()?, qnh))
+    }
+
+    // Derived values reported by `Sensor::measure`
+    pub fn set_pressure_ref(&mut self, pref : PressureRef) {
+        self.pref = pref;
+    }
+
+    pub fn read_temperature(&mut self) -> Result<f32> {
+        self.wait_data(lps25h_status_msks::T_DA)?;
+        let raw = self.i2c.smbus_read_i2c_block_data(LPS25H_AUTO_INC | Lps25hReg::TempOutL as u8, 2)?;
+        let t = to_i16(raw[1], raw[0]);
+        Ok(LPS25H_TEMP_OFFSET + t as f32 / LPS25H_TEMP_LSB_PER_DEGC)
+    }
+
+    // Reference pressure (REF_P) in Pa, subtracted from the output when
+    // differential mode is enabled.
+    pub fn set_reference_pressure(&mut self, pa : f32) -> Result<()> {
+        let raw = (pa / 100.0 * LPS25H_PRESS_LSB_PER_HPA).round() as i32;
+        self.i2c.smbus_write_i2c_block_data(
+            LPS25H_AUTO_INC | Lps25hReg::RefPXl as u8,
+            &[raw as u8, (raw >> 8) as u8, (raw >> 16) as u8],
+        )
+    }
+
+    pub fn get_reference_pressure(&mut self) -> Result<f32> {
+        let raw = self.i2c.smbus_read_i2c_block_data(LPS25H_AUTO_INC | Lps25hReg::RefPXl as u8, 3)?;
+        let p = (((raw[2] as u32) << 24) | ((raw[1] as u32) << 16) | ((raw[0] as u32) << 8)) as i32 >> 8;
+        Ok(p as f32 / LPS25H_PRESS_LSB_PER_HPA * 100.0)
+    }
+
+    pub fn set_differential(&mut self, enable : bool) -> Result<()> {
+        let mut ctrl1 = self.i2c.smbus_read_byte_data(Lps25hReg::CtrlReg1 as u8)?;
+        if enable {
+            ctrl1 |= lps25h_ctrl1_msks::DIFF_EN;
+        } else {
+            ctrl1 &= !lps25h_ctrl1_msks::DIFF_EN;
+        }
+        self.i2c.smbus_write_byte_data(Lps25hReg::CtrlReg1 as u8, ctrl1)
+    }
+
+    // Pressure offset (RPDS) in Pa, added to every output for one-point
+    // calibration. One LSB is 1/16 hPa.
+    pub fn set_pressure_offset(&mut self, pa : f32) -> Result<()> {
+        let raw = (pa / 100.0 * 16.0).round() as i16;
+        self.i2c.smbus_write_i2c_block_data(
+            LPS25H_AUTO_INC | Lps25hReg::RpdsL as u8,
+            &[raw as u8, (raw >> 8) as u8],
+        )
+    }
+
+    pub fn get_pressure_offset(&mut self) -> Result<f32> {
+        let raw = self.i2c.smbus_read_i2c_block_data(LPS25H_AUTO_INC | Lps25hReg::RpdsL as u8, 2)?;
+        Ok(to_i16(raw[1], raw[0]) as f32 / 16.0 * 100.0)
+    }
+
+    // Number of samples currently stored in the FIFO
+    pub fn fifo_level(&mut self) -> Result<u8> {
+        Ok(self.i2c.smbus_read_byte_data(Lps25hReg::FifoStatus as u8)? & 0x1F)
+    }
+}
+
+pub const PRESS_TEMP_QUANTITIES : [Quantity; 4] = [Quantity::Temperature, Quantity::Pressure, Quantity::SeaLevelPressure, Quantity::Altitude];
+
+impl<D : I2CDevice + Send> Sensor for BMP180<D> {
+    fn id(&self) -> &str {
+        BMP180_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &PRESS_TEMP_QUANTITIES
+    }
+
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let oss = self.oss;
+        let (t, p) = self.read(oss)?;
+        let mut m = vec![
+            Measurement::new(self.id(), Quantity::Temperature, Unit::DegC,   t),
+            Measurement::new(self.id(), Quantity::Pressure,    Unit::Pascal, p),
+        ];
+        m.extend(self.pref.measurements(self.id(), p as f32));
+        Ok(m)
+    }
+}
+
+impl<D : I2CDevice + Send> Sensor for LPS25H<D> {
+    fn id(&self) -> &str {
+        LPS25H_ID_STR
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &PRESS_TEMP_QUANTITIES
+    }
+
+    fn measure(&mut self) -> Result<Vec<Measurement>> {
+        let t = self.read_temperature()?;
+        let p = self.read_pressure()?;
+        let mut m = vec![
+            Measurement::new(self.id(), Quantity::Temperature, Unit::DegC,   t),
+            Measurement::new(self.id(), Quantity::Pressure,    Unit::Pascal, p),
+        ];
+        m.extend(self.pref.measurements(self.id(), p));
+        Ok(m)
+    }
+}
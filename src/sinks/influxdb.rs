@@ -0,0 +1,76 @@
+use reqwest::header::AUTHORIZATION;
+use reqwest::Client;
+use serde_json::Value;
+
+use super::{Sample, SinkError, TelemetrySink, TIMEOUT};
+
+#[derive(Clone, Debug)]
+pub struct InfluxDbConf {
+    pub url         : String,
+    pub database    : String,
+    pub measurement : String,
+    // Sent as `Authorization: Token <token>` when set
+    pub token       : Option<String>,
+}
+
+// InfluxDB 1.x write API (also served by 2.x) : POST {url}/write?db=..&precision=ms
+// with one line per sample :
+//   <measurement> <key>=<value>,<key>=<value> <ts>
+pub struct InfluxDb {
+    client : Client,
+    conf   : InfluxDbConf,
+}
+
+impl InfluxDb {
+    pub fn new(conf : InfluxDbConf) -> InfluxDb {
+        InfluxDb {
+            client : Client::builder().timeout(TIMEOUT).build().unwrap_or_else(|_| Client::new()),
+            conf,
+        }
+    }
+}
+
+fn escape(s : &str, special : &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub fn line(measurement : &str, sample : &Sample) -> Option<String> {
+    let fields : Vec<String> = sample.values.iter()
+        .filter_map(|(k, v)| {
+            let v = match v {
+                Value::Number(n) => n.as_f64()?.to_string(),
+                Value::Bool(b) => b.to_string(),
+                Value::String(s) => format!("\"{}\"", escape(s, &['"', '\\'])),
+                _ => return None,
+            };
+            Some(format!("{}={}", escape(k, &[',', '=', ' ']), v))
+        })
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    Some(format!("{} {} {}", escape(measurement, &[',', ' ']), fields.join(","), sample.ts.timestamp_millis()))
+}
+
+impl TelemetrySink for InfluxDb {
+    fn send(&mut self, sample : &Sample) -> Result<(), SinkError> {
+        let body = match line(&self.conf.measurement, sample) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let url = format!("{}/write?db={}&precision=ms", self.conf.url.trim_end_matches('/'), self.conf.database);
+        let mut req = self.client.post(&url).body(body);
+        if let Some(ref token) = self.conf.token {
+            req = req.header(AUTHORIZATION, format!("Token {}", token));
+        }
+        req.send()?.error_for_status()?;
+        Ok(())
+    }
+}
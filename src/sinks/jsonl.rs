@@ -0,0 +1,37 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use super::{Sample, SinkError, TelemetrySink};
+
+// One `{"ts":..,"values":{..}}` document per line, appended to a file or
+// written to stdout
+pub struct JsonLines {
+    path : Option<PathBuf>,
+}
+
+impl JsonLines {
+    pub fn new(path : Option<PathBuf>) -> JsonLines {
+        JsonLines { path }
+    }
+}
+
+impl TelemetrySink for JsonLines {
+    fn send(&mut self, sample : &Sample) -> Result<(), SinkError> {
+        let line = sample.to_json().to_string();
+        match self.path {
+            Some(ref path) => {
+                // Reopened each time so that the file can be rotated
+                let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+                writeln!(file, "{}", line)?;
+            },
+            None => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                writeln!(out, "{}", line)?;
+                out.flush()?;
+            },
+        }
+        Ok(())
+    }
+}
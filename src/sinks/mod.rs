@@ -0,0 +1,178 @@
+pub mod thingsboard;
+pub mod mqtt;
+pub mod influxdb;
+pub mod jsonl;
+
+use chrono::prelude::*;
+use serde_json::{json, Map, Value};
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::outbox::{Backoff, Outbox};
+use crate::sampling::Snapshot;
+
+pub use self::influxdb::{InfluxDb, InfluxDbConf};
+pub use self::jsonl::JsonLines;
+pub use self::mqtt::{Mqtt, MqttConf, MqttMode};
+pub use self::thingsboard::ThingsBoard;
+
+// Bound on connecting to and waiting for a remote sink, shared by the network
+// sinks so that none holds up the sampling loop for long
+const TIMEOUT : Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum SinkError {
+    Io(io::Error),
+    Http(reqwest::Error),
+    Protocol(String),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkError::Io(e) => write!(f, "{}", e),
+            SinkError::Http(e) => write!(f, "{}", e),
+            SinkError::Protocol(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl error::Error for SinkError {}
+
+impl From<io::Error> for SinkError {
+    fn from(e : io::Error) -> SinkError {
+        SinkError::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for SinkError {
+    fn from(e : reqwest::Error) -> SinkError {
+        SinkError::Http(e)
+    }
+}
+
+// Values of one cycle keyed by telemetry key, with the time they were taken
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub ts     : DateTime<Utc>,
+    pub values : Map<String, Value>,
+}
+
+impl Sample {
+    pub fn from_snapshot(snapshot : &Snapshot) -> Sample {
+        Sample {
+            ts     : snapshot.ts(),
+            values : snapshot.values(),
+        }
+    }
+
+    // ThingsBoard's timestamped telemetry shape, also used in the outboxes
+    pub fn to_json(&self) -> Value {
+        json!({
+            "ts"     : self.ts.timestamp_millis(),
+            "values" : self.values,
+        })
+    }
+
+    pub fn from_json(line : &str) -> Option<Sample> {
+        let v : Value = serde_json::from_str(line).ok()?;
+        let ts = v.get("ts")?.as_i64()?;
+        let values = v.get("values")?.as_object()?.clone();
+        // Not `timestamp_millis_opt`, which overflows on negative values
+        let ts = Utc.timestamp_opt(ts.div_euclid(1000), (ts.rem_euclid(1000) * 1_000_000) as u32).single()?;
+        Some(Sample { ts, values })
+    }
+}
+
+pub trait TelemetrySink : Send {
+    fn send(&mut self, sample : &Sample) -> Result<(), SinkError>;
+}
+
+pub struct Flush {
+    pub sent  : usize,
+    // Error of the failed attempt and delay before the next one
+    pub error : Option<(Error, Duration)>,
+}
+
+// Store-and-forward wrapper around a sink : every sample goes through the
+// sink's own outbox, which is replayed in order with exponential backoff.
+pub struct Forwarder {
+    name    : String,
+    sink    : Box<dyn TelemetrySink>,
+    outbox  : Outbox,
+    backoff : Backoff,
+}
+
+impl Forwarder {
+    pub fn new(name : &str, sink : Box<dyn TelemetrySink>, outbox : Outbox, backoff : Backoff) -> Forwarder {
+        Forwarder { name : name.to_owned(), sink, outbox, backoff }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn queued(&self) -> usize {
+        self.outbox.len()
+    }
+
+    // Queues a sample, returns how many old ones had to be dropped
+    pub fn push(&mut self, sample : &Sample) -> io::Result<usize> {
+        self.outbox.push(&sample.to_json())
+    }
+
+    pub fn flush(&mut self, now : Instant) -> io::Result<Flush> {
+        let mut flush = Flush { sent : 0, error : None };
+        if !self.backoff.ready(now) {
+            return Ok(flush);
+        }
+        while let Some(line) = self.outbox.front() {
+            let sample = match Sample::from_json(line) {
+                Some(sample) => sample,
+                None => {
+                    self.outbox.pop();
+                    continue;
+                },
+            };
+            match self.sink.send(&sample) {
+                Ok(()) => {
+                    self.outbox.pop();
+                    self.backoff.succeeded();
+                    flush.sent += 1;
+                },
+                Err(e) => {
+                    let err = Error::Sink { sink : self.name.clone(), err : e };
+                    flush.error = Some((err, self.backoff.failed(now)));
+                    break;
+                },
+            }
+        }
+        self.outbox.commit()?;
+        Ok(flush)
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.backoff.failures()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SinkConf {
+    ThingsBoard { url : String },
+    Mqtt(MqttConf),
+    InfluxDb(InfluxDbConf),
+    // `None` writes to stdout
+    JsonLines { path : Option<std::path::PathBuf> },
+}
+
+pub fn open(conf : &SinkConf) -> Box<dyn TelemetrySink> {
+    match conf {
+        SinkConf::ThingsBoard { url } => Box::new(ThingsBoard::new(url)),
+        SinkConf::Mqtt(mc) => Box::new(Mqtt::new(mc.clone())),
+        SinkConf::InfluxDb(ic) => Box::new(InfluxDb::new(ic.clone())),
+        SinkConf::JsonLines { path } => Box::new(JsonLines::new(path.clone())),
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::prelude::*;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use super::{Sample, SinkError, TelemetrySink, TIMEOUT};
+
+// Minimal MQTT 3.1.1 publisher : clean session, QoS 1, one connection kept
+// open between samples and reopened after an error or once idle for longer
+// than the keep alive (the broker may have dropped it by then).
+//
+// In plain mode a sample is one PUBLISH per key. When it fails partway, the
+// keys already acknowledged are skipped when the same sample is sent again,
+// so they are not published twice. A key whose PUBACK was lost is still
+// published again, as QoS 1 allows.
+const THINGSBOARD_TOPIC : &str = "v1/devices/me/telemetry";
+
+mod packet {
+    pub const CONNECT : u8 = 0x10;
+    pub const CONNACK : u8 = 0x20;
+    pub const PUBLISH_QOS1 : u8 = 0x32;
+    pub const PUBACK : u8 = 0x40;
+    pub const DISCONNECT : u8 = 0xE0;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MqttMode {
+    // The whole sample on `v1/devices/me/telemetry`, access token as user name
+    ThingsBoard,
+    // Every value as text on `<topic>/<key>`
+    Plain { topic : String },
+}
+
+#[derive(Clone, Debug)]
+pub struct MqttConf {
+    pub host       : String,
+    pub client_id  : String,
+    pub username   : Option<String>,
+    pub password   : Option<String>,
+    pub keep_alive : Duration,
+    pub mode       : MqttMode,
+}
+
+pub struct Mqtt {
+    conf      : MqttConf,
+    conn      : Option<TcpStream>,
+    last_used : Instant,
+    packet_id : u16,
+    // Sample being published in plain mode and its keys acknowledged so far
+    acked     : Option<(DateTime<Utc>, usize)>,
+}
+
+fn put_str(buf : &mut Vec<u8>, s : &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn frame(kind : u8, body : &[u8]) -> Vec<u8> {
+    let mut buf = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    buf.extend_from_slice(body);
+    buf
+}
+
+fn read_packet(stream : &mut TcpStream) -> Result<(u8, Vec<u8>), SinkError> {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte)?;
+    let kind = byte[0];
+    let mut len = 0usize;
+    for shift in 0..4 {
+        let mut b = [0u8; 1];
+        stream.read_exact(&mut b)?;
+        len |= ((b[0] & 0x7F) as usize) << (7 * shift);
+        if b[0] & 0x80 == 0 {
+            let mut body = vec![0u8; len];
+            stream.read_exact(&mut body)?;
+            return Ok((kind, body));
+        }
+    }
+    Err(SinkError::Protocol("MQTT : malformed remaining length".to_owned()))
+}
+
+impl Mqtt {
+    pub fn new(conf : MqttConf) -> Mqtt {
+        Mqtt { conf, conn : None, last_used : Instant::now(), packet_id : 0, acked : None }
+    }
+
+    // Tries each address of the host in turn, each within `TIMEOUT`
+    fn open(&self) -> io::Result<TcpStream> {
+        let mut err = io::Error::new(io::ErrorKind::NotFound, format!("MQTT : {} has no address", self.conf.host));
+        for addr in self.conf.host.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => err = e,
+            }
+        }
+        Err(err)
+    }
+
+    fn connect(&self) -> Result<TcpStream, SinkError> {
+        let mut stream = self.open()?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut flags = 0x02;
+        if self.conf.username.is_some() {
+            flags |= 0x80;
+        }
+        if self.conf.password.is_some() {
+            flags |= 0x40;
+        }
+        let mut body = Vec::new();
+        put_str(&mut body, "MQTT");
+        body.push(4);
+        body.push(flags);
+        body.extend_from_slice(&(self.conf.keep_alive.as_secs().min(u16::MAX as u64) as u16).to_be_bytes());
+        put_str(&mut body, &self.conf.client_id);
+        if let Some(ref user) = self.conf.username {
+            put_str(&mut body, user);
+        }
+        if let Some(ref pass) = self.conf.password {
+            put_str(&mut body, pass);
+        }
+        stream.write_all(&frame(packet::CONNECT, &body))?;
+
+        match read_packet(&mut stream)? {
+            (packet::CONNACK, ref body) if body.len() == 2 => match body[1] {
+                0 => Ok(stream),
+                rc => Err(SinkError::Protocol(format!("MQTT : connection refused by {} (return code {})", self.conf.host, rc))),
+            },
+            (kind, _) => Err(SinkError::Protocol(format!("MQTT : expected CONNACK, got packet 0x{:02X}", kind))),
+        }
+    }
+
+    fn publish(&mut self, topic : &str, payload : &[u8]) -> Result<(), SinkError> {
+        self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+        let id = self.packet_id;
+        let stream = match self.conn {
+            Some(ref mut stream) => stream,
+            None => return Err(SinkError::Protocol("MQTT : not connected".to_owned())),
+        };
+
+        let mut body = Vec::new();
+        put_str(&mut body, topic);
+        body.extend_from_slice(&id.to_be_bytes());
+        body.extend_from_slice(payload);
+        stream.write_all(&frame(packet::PUBLISH_QOS1, &body))?;
+
+        loop {
+            match read_packet(stream)? {
+                (packet::PUBACK, ref body) if body.len() == 2 && u16::from_be_bytes([body[0], body[1]]) == id => return Ok(()),
+                // Nothing else is expected with a clean session and no subscription
+                _ => continue,
+            }
+        }
+    }
+
+    fn publish_sample(&mut self, sample : &Sample) -> Result<(), SinkError> {
+        match self.conf.mode.clone() {
+            MqttMode::ThingsBoard => self.publish(THINGSBOARD_TOPIC, sample.to_json().to_string().as_bytes()),
+            MqttMode::Plain { topic } => {
+                let done = match self.acked {
+                    Some((ts, n)) if ts == sample.ts => n,
+                    _ => 0,
+                };
+                for (i, (key, value)) in sample.values.iter().enumerate().skip(done) {
+                    let text = match value.as_str() {
+                        Some(s) => s.to_owned(),
+                        None => value.to_string(),
+                    };
+                    self.publish(&format!("{}/{}", topic, key), text.as_bytes())?;
+                    self.acked = Some((sample.ts, i + 1));
+                }
+                self.acked = None;
+                Ok(())
+            },
+        }
+    }
+}
+
+impl TelemetrySink for Mqtt {
+    fn send(&mut self, sample : &Sample) -> Result<(), SinkError> {
+        let keep_alive = self.conf.keep_alive;
+        if self.conn.is_some() && keep_alive > Duration::from_secs(0) && self.last_used.elapsed() >= keep_alive {
+            self.conn = None;
+        }
+        if self.conn.is_none() {
+            self.conn = Some(self.connect()?);
+        }
+        let res = self.publish_sample(sample);
+        if res.is_err() {
+            self.conn = None;
+        }
+        self.last_used = Instant::now();
+        res
+    }
+}
+
+impl Drop for Mqtt {
+    fn drop(&mut self) {
+        if let Some(ref mut stream) = self.conn {
+            let _ = stream.write_all(&frame(packet::DISCONNECT, &[]));
+        }
+    }
+}
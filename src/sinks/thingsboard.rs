@@ -0,0 +1,30 @@
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+
+use super::{Sample, SinkError, TelemetrySink, TIMEOUT};
+
+// ThingsBoard HTTP device API : POST {host}/api/v1/{token}/telemetry
+pub struct ThingsBoard {
+    client : Client,
+    url    : String,
+}
+
+impl ThingsBoard {
+    pub fn new(url : &str) -> ThingsBoard {
+        ThingsBoard {
+            client : Client::builder().timeout(TIMEOUT).build().unwrap_or_else(|_| Client::new()),
+            url    : url.to_owned(),
+        }
+    }
+}
+
+impl TelemetrySink for ThingsBoard {
+    fn send(&mut self, sample : &Sample) -> Result<(), SinkError> {
+        self.client.post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(sample.to_json().to_string())
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,483 @@
+use chrono::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::sampling::Snapshot;
+
+// On-disk time-series store. Every series (one telemetry key) gets an id from
+// `series.txt`; samples are appended to `raw.dat` and rolled up into 1-minute,
+// 1-hour and 1-day min/avg/max aggregates in `1m.dat`, `1h.dat` and `1d.dat`.
+// All records are fixed size and little-endian:
+//   raw : ts (i64, s) | series (u16) | value (f64)
+//   agg : start (i64, s) | series (u16) | count (u32) | min | avg | max (f64)
+
+const SERIES_FILE : &str = "series.txt";
+const RAW_REC_LEN : usize = 8 + 2 + 8;
+const AGG_REC_LEN : usize = 8 + 2 + 4 + 8 * 3;
+const COMPACTION_PERIOD : i64 = 3600;
+const INDEX_STEP  : u64 = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Resolution {
+    Raw,
+    Minute,
+    Hour,
+    Day,
+}
+
+const RESOLUTIONS : [Resolution; 4] = [Resolution::Raw, Resolution::Minute, Resolution::Hour, Resolution::Day];
+
+impl Resolution {
+    pub fn seconds(self) -> i64 {
+        match self {
+            Resolution::Raw    => 0,
+            Resolution::Minute => 60,
+            Resolution::Hour   => 3600,
+            Resolution::Day    => 86400,
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Resolution::Raw    => "raw.dat",
+            Resolution::Minute => "1m.dat",
+            Resolution::Hour   => "1h.dat",
+            Resolution::Day    => "1d.dat",
+        }
+    }
+
+    fn rec_len(self) -> usize {
+        match self {
+            Resolution::Raw => RAW_REC_LEN,
+            _               => AGG_REC_LEN,
+        }
+    }
+
+    fn next(self) -> Option<Resolution> {
+        match self {
+            Resolution::Raw    => Some(Resolution::Minute),
+            Resolution::Minute => Some(Resolution::Hour),
+            Resolution::Hour   => Some(Resolution::Day),
+            Resolution::Day    => None,
+        }
+    }
+
+    fn bucket(self, ts : i64) -> i64 {
+        ts - ts.rem_euclid(self.seconds())
+    }
+}
+
+// A raw sample is a point with `count == 1` and min == avg == max
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point {
+    pub ts    : i64,
+    pub count : u32,
+    pub min   : f64,
+    pub avg   : f64,
+    pub max   : f64,
+}
+
+impl Point {
+    fn sample(ts : i64, value : f64) -> Point {
+        Point { ts, count : 1, min : value, avg : value, max : value }
+    }
+
+    fn merge(&mut self, other : &Point) {
+        let count = self.count + other.count;
+        self.avg = (self.avg * self.count as f64 + other.avg * other.count as f64) / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+// How long records of each resolution are kept, `None` keeping them forever
+#[derive(Copy, Clone, Debug)]
+pub struct Retention {
+    pub raw    : Option<Duration>,
+    pub minute : Option<Duration>,
+    pub hour   : Option<Duration>,
+    pub day    : Option<Duration>,
+}
+
+impl Retention {
+    fn get(&self, res : Resolution) -> Option<Duration> {
+        match res {
+            Resolution::Raw    => self.raw,
+            Resolution::Minute => self.minute,
+            Resolution::Hour   => self.hour,
+            Resolution::Day    => self.day,
+        }
+    }
+}
+
+fn encode(res : Resolution, series : u16, p : &Point) -> Vec<u8> {
+    let mut rec = Vec::with_capacity(res.rec_len());
+    rec.extend_from_slice(&p.ts.to_le_bytes());
+    rec.extend_from_slice(&series.to_le_bytes());
+    if res == Resolution::Raw {
+        rec.extend_from_slice(&p.avg.to_le_bytes());
+    } else {
+        rec.extend_from_slice(&p.count.to_le_bytes());
+        rec.extend_from_slice(&p.min.to_le_bytes());
+        rec.extend_from_slice(&p.avg.to_le_bytes());
+        rec.extend_from_slice(&p.max.to_le_bytes());
+    }
+    rec
+}
+
+fn decode(res : Resolution, rec : &[u8]) -> (u16, Point) {
+    let i64_at = |i : usize| { let mut b = [0; 8]; b.copy_from_slice(&rec[i..i + 8]); i64::from_le_bytes(b) };
+    let f64_at = |i : usize| { let mut b = [0; 8]; b.copy_from_slice(&rec[i..i + 8]); f64::from_le_bytes(b) };
+    let ts = i64_at(0);
+    let series = u16::from_le_bytes([rec[8], rec[9]]);
+    if res == Resolution::Raw {
+        (series, Point::sample(ts, f64_at(10)))
+    } else {
+        let count = u32::from_le_bytes([rec[10], rec[11], rec[12], rec[13]]);
+        (series, Point { ts, count, min : f64_at(14), avg : f64_at(22), max : f64_at(30) })
+    }
+}
+
+// Calls `f` with each record of `file` from record `start` on, reading them
+// one at a time
+fn scan<F>(mut file : File, res : Resolution, start : u64, mut f : F) -> io::Result<()>
+    where F : FnMut(u16, Point) -> io::Result<()>
+{
+    file.seek(SeekFrom::Start(start * res.rec_len() as u64))?;
+    let mut reader = BufReader::new(file);
+    let mut rec = vec![0; res.rec_len()];
+    loop {
+        match reader.read_exact(&mut rec) {
+            Ok(()) => {
+                let (series, p) = decode(res, &rec);
+                f(series, p)?;
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Sparse time index of a file. Records are appended roughly in time order,
+// `marks[k]` is the latest timestamp of the records before record
+// `k * INDEX_STEP`, so a query can skip every step that ends before it starts.
+struct TimeIndex {
+    len    : u64,
+    min_ts : i64,
+    max_ts : i64,
+    marks  : Vec<i64>,
+}
+
+impl TimeIndex {
+    fn new() -> TimeIndex {
+        TimeIndex { len : 0, min_ts : i64::MAX, max_ts : i64::MIN, marks : Vec::new() }
+    }
+
+    fn push(&mut self, ts : i64) {
+        if self.len.is_multiple_of(INDEX_STEP) {
+            self.marks.push(self.max_ts);
+        }
+        self.min_ts = self.min_ts.min(ts);
+        self.max_ts = self.max_ts.max(ts);
+        self.len += 1;
+    }
+
+    // First record that may be at or after `from`
+    fn seek(&self, from : i64) -> u64 {
+        match self.marks.iter().position(|m| *m >= from) {
+            Some(0) => 0,
+            Some(k) => (k as u64 - 1) * INDEX_STEP,
+            None    => self.marks.len().saturating_sub(1) as u64 * INDEX_STEP,
+        }
+    }
+}
+
+// Records of one series read from a file opened by `Store::select`, so that
+// the store does not need to stay locked while they are decoded
+pub struct Selection {
+    file    : Option<File>,
+    res     : Resolution,
+    series  : u16,
+    start   : u64,
+    end     : u64,
+    from    : i64,
+    to      : i64,
+    partial : Option<Point>,
+}
+
+impl Selection {
+    pub fn read(self) -> io::Result<Vec<Point>> {
+        let mut points = Vec::new();
+        if let Some(mut file) = self.file {
+            let rec_len = self.res.rec_len() as u64;
+            file.seek(SeekFrom::Start(self.start * rec_len))?;
+            let mut reader = BufReader::new(file.take((self.end - self.start) * rec_len));
+            let mut rec = vec![0; rec_len as usize];
+            for _ in self.start..self.end {
+                reader.read_exact(&mut rec)?;
+                if u16::from_le_bytes([rec[8], rec[9]]) != self.series {
+                    continue;
+                }
+                let (_, p) = decode(self.res, &rec);
+                if p.ts >= self.from && p.ts < self.to {
+                    points.push(p);
+                }
+            }
+        }
+        if let Some(p) = self.partial {
+            if p.ts >= self.from && p.ts < self.to {
+                points.push(p);
+            }
+        }
+        points.sort_by_key(|p| p.ts);
+        Ok(points)
+    }
+}
+
+pub struct Store {
+    dir       : PathBuf,
+    retention : Retention,
+    series    : Vec<String>,
+    files     : HashMap<Resolution, File>,
+    index     : HashMap<Resolution, TimeIndex>,
+    pending   : HashMap<(Resolution, u16), Point>,
+    last_compaction : i64,
+}
+
+impl Store {
+    pub fn open<P : AsRef<Path>>(dir : P, retention : Retention) -> io::Result<Store> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        let series = match File::open(dir.join(SERIES_FILE)) {
+            Ok(f) => BufReader::new(f).lines().collect::<io::Result<Vec<String>>>()?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut files = HashMap::new();
+        for res in RESOLUTIONS.iter() {
+            let file = OpenOptions::new().create(true).append(true).open(dir.join(res.file_name()))?;
+            // Drop a partially written record left by a power cut
+            let len = file.metadata()?.len();
+            file.set_len(len - len % res.rec_len() as u64)?;
+            files.insert(*res, file);
+        }
+
+        let mut store = Store {
+            dir,
+            retention,
+            series,
+            files,
+            index   : HashMap::new(),
+            pending : HashMap::new(),
+            last_compaction : 0,
+        };
+        store.replay()?;
+        Ok(store)
+    }
+
+    // Rebuilds the in-progress buckets from the finer resolution files, and
+    // finalizes the buckets left open when the process was stopped.
+    fn replay(&mut self) -> io::Result<()> {
+        // End of the last bucket written, by resolution and series
+        let mut done : HashMap<(Resolution, u16), i64> = HashMap::new();
+        let mut seen = HashSet::new();
+        for res in RESOLUTIONS.iter() {
+            let mut index = TimeIndex::new();
+            scan(File::open(self.dir.join(res.file_name()))?, *res, 0, |s, p| {
+                index.push(p.ts);
+                seen.insert((*res, s));
+                if *res != Resolution::Raw {
+                    let end = done.entry((*res, s)).or_insert(i64::MIN);
+                    *end = (*end).max(p.ts + res.seconds());
+                }
+                Ok(())
+            })?;
+            self.index.insert(*res, index);
+        }
+
+        // Only the records after the buckets written at the next resolution
+        // are rolled up again
+        for res in [Resolution::Hour, Resolution::Minute, Resolution::Raw].iter() {
+            let next = res.next().unwrap();
+            let from = seen.iter()
+                .filter(|(r, _)| r == res)
+                .map(|(_, s)| *done.get(&(next, *s)).unwrap_or(&i64::MIN))
+                .min();
+            let start = match from {
+                Some(from) => self.index[res].seek(from),
+                None => continue,
+            };
+            scan(File::open(self.dir.join(res.file_name()))?, *res, start, |s, p| {
+                if p.ts >= *done.get(&(next, s)).unwrap_or(&i64::MIN) {
+                    self.roll_up(next, s, p)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, res : Resolution, series : u16, p : &Point) -> io::Result<()> {
+        let rec = encode(res, series, p);
+        self.files.get_mut(&res).unwrap().write_all(&rec)?;
+        self.index.get_mut(&res).unwrap().push(p.ts);
+        Ok(())
+    }
+
+    fn roll_up(&mut self, res : Resolution, series : u16, p : Point) -> io::Result<()> {
+        let start = res.bucket(p.ts);
+        match self.pending.get(&(res, series)).cloned() {
+            Some(mut acc) if acc.ts == start => {
+                acc.merge(&p);
+                self.pending.insert((res, series), acc);
+            },
+            prev => {
+                self.pending.insert((res, series), Point { ts : start, ..p });
+                if let Some(acc) = prev {
+                    self.write(res, series, &acc)?;
+                    if let Some(next) = res.next() {
+                        self.roll_up(next, series, acc)?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Finalizes the buckets of `series` that end before `ts`, so that an
+    // aggregate does not wait for the next bucket of the finer resolution.
+    fn advance(&mut self, series : u16, ts : i64) -> io::Result<()> {
+        for res in RESOLUTIONS[1..].iter() {
+            let acc = match self.pending.get(&(*res, series)) {
+                Some(acc) if acc.ts < res.bucket(ts) => *acc,
+                _ => continue,
+            };
+            self.pending.remove(&(*res, series));
+            self.write(*res, series, &acc)?;
+            if let Some(next) = res.next() {
+                self.roll_up(next, series, acc)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn series_id(&mut self, key : &str) -> io::Result<u16> {
+        if let Some(id) = self.series.iter().position(|s| s == key) {
+            return Ok(id as u16);
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(self.dir.join(SERIES_FILE))?;
+        writeln!(file, "{}", key)?;
+        self.series.push(key.to_owned());
+        Ok((self.series.len() - 1) as u16)
+    }
+
+    pub fn append(&mut self, key : &str, ts : DateTime<Utc>, value : f64) -> io::Result<()> {
+        let series = self.series_id(key)?;
+        let p = Point::sample(ts.timestamp(), value);
+        self.write(Resolution::Raw, series, &p)?;
+        self.advance(series, p.ts)?;
+        self.roll_up(Resolution::Minute, series, p)?;
+
+        if p.ts - self.last_compaction >= COMPACTION_PERIOD {
+            self.last_compaction = p.ts;
+            self.enforce_retention(ts)?;
+        }
+        Ok(())
+    }
+
+    // Every reading of a snapshot, under the snapshot's keys, which stay the
+    // same whichever sensors it holds
+    pub fn append_snapshot(&mut self, snapshot : &Snapshot) -> io::Result<()> {
+        for (key, m) in snapshot.iter() {
+            self.append(key, m.timestamp, m.value)?;
+        }
+        Ok(())
+    }
+
+    pub fn series(&self) -> &[String] {
+        &self.series
+    }
+
+    // Points of `key` with `from <= ts < to`, including the bucket still
+    // being aggregated.
+    pub fn query(&self, key : &str, res : Resolution, from : DateTime<Utc>, to : DateTime<Utc>) -> io::Result<Vec<Point>> {
+        self.select(key, res, from, to)?.read()
+    }
+
+    // Same in two steps : the records written so far are located here, and
+    // read with `Selection::read` once the store can be released.
+    pub fn select(&self, key : &str, res : Resolution, from : DateTime<Utc>, to : DateTime<Utc>) -> io::Result<Selection> {
+        let (from, to) = (from.timestamp(), to.timestamp());
+        let mut sel = Selection { file : None, res, series : 0, start : 0, end : 0, from, to, partial : None };
+        let series = match self.series.iter().position(|s| s == key) {
+            Some(id) => id as u16,
+            None => return Ok(sel),
+        };
+        let index = &self.index[&res];
+        sel.series = series;
+        sel.start = index.seek(from);
+        sel.end = index.len;
+        // The open file outlives a rewrite by `enforce_retention`
+        sel.file = Some(File::open(self.dir.join(res.file_name()))?);
+        sel.partial = self.partial(res, series);
+        Ok(sel)
+    }
+
+    // Bucket of `res` still being aggregated, with the samples that have not
+    // been rolled up from the finer resolutions yet.
+    fn partial(&self, res : Resolution, series : u16) -> Option<Point> {
+        let mut partial = self.pending.get(&(res, series)).cloned();
+        for finer in RESOLUTIONS[1..].iter().filter(|r| r.seconds() < res.seconds()) {
+            if let Some(p) = self.pending.get(&(*finer, series)) {
+                match partial {
+                    Some(ref mut acc) if acc.ts == res.bucket(p.ts) => acc.merge(p),
+                    None => partial = Some(Point { ts : res.bucket(p.ts), ..*p }),
+                    _ => (),
+                }
+            }
+        }
+        partial
+    }
+
+    // Drops records older than their resolution's retention by rewriting the
+    // files. Called hourly from `append`.
+    pub fn enforce_retention(&mut self, now : DateTime<Utc>) -> io::Result<()> {
+        for res in RESOLUTIONS.iter() {
+            let keep = match self.retention.get(*res) {
+                Some(keep) => keep,
+                None => continue,
+            };
+            let limit = now.timestamp() - keep.as_secs() as i64;
+            let index = &self.index[res];
+            if index.min_ts >= limit {
+                continue;
+            }
+
+            // Records before `cut` are all dropped, the tail is copied
+            // without the old ones it still holds
+            let cut = if index.max_ts < limit { index.len } else { index.seek(limit) };
+            let path = self.dir.join(res.file_name());
+            let tmp = path.with_extension("tmp");
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            let mut kept = TimeIndex::new();
+            scan(File::open(&path)?, *res, cut, |s, p| {
+                if p.ts >= limit {
+                    out.write_all(&encode(*res, s, &p))?;
+                    kept.push(p.ts);
+                }
+                Ok(())
+            })?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            fs::rename(&tmp, &path)?;
+            self.files.insert(*res, OpenOptions::new().append(true).open(&path)?);
+            self.index.insert(*res, kept);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use chrono::prelude::*;
+use serde_json::{json, Map, Value};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::sampling::{Health, Snapshot};
+use crate::storage::{Resolution, Selection, Store};
+
+// Embedded web server serving the dashboard and its JSON API:
+//   GET /                  dashboard
+//   GET /api/current       latest value of every series
+//   GET /api/series        keys of the stored series
+//   GET /api/health        state of each sensor worker
+//   GET /api/history       ?key=<series>[&from=<unix s>][&to=<unix s>][&res=raw|1m|1h|1d]
+
+const DASHBOARD : &str = include_str!("dashboard.html");
+const DEFAULT_SPAN : i64 = 24 * 3600;
+
+pub struct WebState {
+    pub latest : Mutex<Option<Arc<Snapshot>>>,
+    pub health : Mutex<Vec<Health>>,
+    pub store  : Option<Arc<Mutex<Store>>>,
+}
+
+impl WebState {
+    pub fn new(store : Option<Arc<Mutex<Store>>>) -> WebState {
+        WebState {
+            latest : Mutex::new(None),
+            health : Mutex::new(Vec::new()),
+            store,
+        }
+    }
+
+    pub fn set_latest(&self, snapshot : Arc<Snapshot>) {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = Some(snapshot);
+    }
+
+    pub fn set_health(&self, health : Vec<Health>) {
+        *self.health.lock().unwrap_or_else(|e| e.into_inner()) = health;
+    }
+}
+
+pub struct Reply {
+    pub status       : u16,
+    pub content_type : &'static str,
+    pub body         : String,
+}
+
+impl Reply {
+    fn json(status : u16, body : Value) -> Reply {
+        Reply { status, content_type : "application/json", body : body.to_string() }
+    }
+
+    fn error(status : u16, msg : &str) -> Reply {
+        Reply::json(status, json!({ "error" : msg }))
+    }
+}
+
+pub fn spawn(addr : &str, state : Arc<WebState>) -> io::Result<thread::JoinHandle<()>> {
+    let server = Server::http(addr).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let reply = if *request.method() == Method::Get {
+                handle(&state, request.url())
+            } else {
+                Reply::error(405, "Only GET is supported")
+            };
+            let mut response = Response::from_data(reply.body.into_bytes()).with_status_code(reply.status);
+            if let Ok(content_type) = Header::from_bytes(&b"Content-Type"[..], reply.content_type.as_bytes()) {
+                response = response.with_header(content_type);
+            }
+            let _ = request.respond(response);
+        }
+    }))
+}
+
+fn query_param<'a>(query : &'a str, name : &str) -> Option<&'a str> {
+    query.split('&')
+        .filter_map(|kv| {
+            let mut it = kv.splitn(2, '=');
+            match (it.next(), it.next()) {
+                (Some(k), Some(v)) if k == name => Some(v),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+fn parse_res(res : &str) -> Option<Resolution> {
+    match res {
+        "raw" => Some(Resolution::Raw),
+        "1m"  => Some(Resolution::Minute),
+        "1h"  => Some(Resolution::Hour),
+        "1d"  => Some(Resolution::Day),
+        _     => None,
+    }
+}
+
+// Finest resolution keeping a chart at a few hundred points
+fn auto_res(span : i64) -> Resolution {
+    if span <= 6 * 3600 {
+        Resolution::Raw
+    } else if span <= 3 * 86400 {
+        Resolution::Minute
+    } else if span <= 90 * 86400 {
+        Resolution::Hour
+    } else {
+        Resolution::Day
+    }
+}
+
+pub fn handle(state : &WebState, url : &str) -> Reply {
+    let mut parts = url.splitn(2, '?');
+    let path = parts.next().unwrap_or("");
+    let query = parts.next().unwrap_or("");
+
+    match path {
+        "/" | "/index.html" => Reply { status : 200, content_type : "text/html; charset=utf-8", body : DASHBOARD.to_owned() },
+        "/api/current" => current(state),
+        "/api/series" => match state.store {
+            Some(ref store) => Reply::json(200, json!(store.lock().unwrap_or_else(|e| e.into_inner()).series())),
+            None => Reply::json(200, json!([])),
+        },
+        "/api/history" => history(state, query),
+        "/api/health" => health(state),
+        _ => Reply::error(404, "Not found"),
+    }
+}
+
+fn current(state : &WebState) -> Reply {
+    let latest = state.latest.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let mut values = Map::new();
+    for (key, m) in latest.iter().flat_map(|snap| snap.iter()) {
+        values.insert(key.to_owned(), json!({
+            "value"     : m.value,
+            "unit"      : m.unit.symbol(),
+            "quantity"  : m.quantity.key(),
+            "sensor"    : m.sensor,
+            "timestamp" : m.timestamp.timestamp(),
+        }));
+    }
+    Reply::json(200, Value::Object(values))
+}
+
+fn health(state : &WebState) -> Reply {
+    let workers : Vec<Value> = state.health.lock().unwrap_or_else(|e| e.into_inner()).iter().map(|h| json!({
+        "sensor"             : h.sensor,
+        "status"             : h.status.as_str(),
+        "interval"           : h.interval.as_secs(),
+        "reads"              : h.reads,
+        "errors"             : h.errors,
+        "consecutive_errors" : h.consecutive_errors,
+        "last_ok"            : h.last_ok.map(|ts| ts.timestamp()),
+        "last_error"         : h.last_error,
+    })).collect();
+    Reply::json(200, json!(workers))
+}
+
+fn history(state : &WebState, query : &str) -> Reply {
+    let store = match state.store {
+        Some(ref store) => store,
+        None => return Reply::error(404, "Local storage is disabled"),
+    };
+    let key = match query_param(query, "key") {
+        Some(key) => key,
+        None => return Reply::error(400, "Missing `key` parameter"),
+    };
+    let now = Utc::now().timestamp();
+    let to = match query_param(query, "to").map(str::parse::<i64>) {
+        Some(Ok(to)) => to,
+        Some(Err(_)) => return Reply::error(400, "Invalid `to` parameter"),
+        None => now + 1,
+    };
+    let from = match query_param(query, "from").map(str::parse::<i64>) {
+        Some(Ok(from)) => from,
+        Some(Err(_)) => return Reply::error(400, "Invalid `from` parameter"),
+        None => to.saturating_sub(DEFAULT_SPAN),
+    };
+    let (from_ts, to_ts) = match (Utc.timestamp_opt(from, 0).single(), Utc.timestamp_opt(to, 0).single()) {
+        (Some(from_ts), Some(to_ts)) => (from_ts, to_ts),
+        _ => return Reply::error(400, "`from` or `to` is out of range"),
+    };
+    if from >= to {
+        return Reply::error(400, "`from` must be before `to`");
+    }
+    let res = match query_param(query, "res") {
+        Some(r) => match parse_res(r) {
+            Some(res) => res,
+            None => return Reply::error(400, "`res` must be one of raw, 1m, 1h, 1d"),
+        },
+        None => auto_res(to - from),
+    };
+
+    // The records are decoded once the store is released for the sampling loop
+    let selection = store.lock().unwrap_or_else(|e| e.into_inner()).select(key, res, from_ts, to_ts);
+    let points = selection.and_then(Selection::read);
+    match points {
+        Ok(points) => {
+            let points : Vec<Value> = points.iter().map(|p| json!({
+                "ts"    : p.ts,
+                "count" : p.count,
+                "min"   : p.min,
+                "avg"   : p.avg,
+                "max"   : p.max,
+            })).collect();
+            Reply::json(200, json!({
+                "key"    : key,
+                "res"    : match res {
+                    Resolution::Raw    => "raw",
+                    Resolution::Minute => "1m",
+                    Resolution::Hour   => "1h",
+                    Resolution::Day    => "1d",
+                },
+                "points" : points,
+            }))
+        },
+        Err(e) => Reply::error(500, &e.to_string()),
+    }
+}
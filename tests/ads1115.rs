@@ -0,0 +1,236 @@
+use i2cdev::core::I2CDevice;
+use rpiapptmon::sensors::ads1115::*;
+use rpiapptmon::sensors::airq::AirQuality;
+use rpiapptmon::sensors::{Quantity, Sensor};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+// ADS1115 register model : 16 bit registers behind a pointer register. A
+// single-shot conversion of mux `m` gives `codes[m]` at once.
+#[derive(Clone, Default)]
+struct Model {
+    state : Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    regs  : [u16; 4],
+    ptr   : u8,
+    codes : [i16; 8],
+    // Every value written to the config register
+    confs : Vec<u16>,
+}
+
+impl Model {
+    fn with_codes(codes : [i16; 8]) -> Model {
+        let model = Model::default();
+        model.state.lock().unwrap().codes = codes;
+        model
+    }
+
+    fn reg(&self, reg : usize) -> u16 {
+        self.state.lock().unwrap().regs[reg]
+    }
+
+    fn conversions(&self) -> usize {
+        self.state.lock().unwrap().confs.iter().filter(|c| *c & 0x8000 != 0).count()
+    }
+}
+
+impl I2CDevice for Model {
+    type Error = io::Error;
+
+    fn read(&mut self, data : &mut [u8]) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        let bytes = state.regs[state.ptr as usize].to_be_bytes();
+        let len = data.len().min(2);
+        data[..len].copy_from_slice(&bytes[..len]);
+        Ok(())
+    }
+
+    fn write(&mut self, data : &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.ptr = data[0] & 0x03;
+        if data.len() == 3 {
+            let val = u16::from_be_bytes([data[1], data[2]]);
+            let ptr = state.ptr as usize;
+            if ptr == 1 {
+                state.confs.push(val);
+                if val & 0x8000 != 0 {
+                    state.regs[0] = state.codes[(val >> 12) as usize & 7] as u16;
+                }
+                // Conversion over
+                state.regs[1] = val | 0x8000;
+            } else {
+                state.regs[ptr] = val;
+            }
+        }
+        Ok(())
+    }
+
+    fn smbus_write_quick(&mut self, _bit : bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn smbus_read_block_data(&mut self, _reg : u8) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "SMBus block read"))
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, reg : u8, len : u8) -> io::Result<Vec<u8>> {
+        self.write(&[reg])?;
+        let mut data = vec![0; len as usize];
+        self.read(&mut data)?;
+        Ok(data)
+    }
+
+    fn smbus_write_block_data(&mut self, _reg : u8, _values : &[u8]) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "SMBus block write"))
+    }
+
+    fn smbus_write_i2c_block_data(&mut self, reg : u8, values : &[u8]) -> io::Result<()> {
+        let mut data = vec![reg];
+        data.extend_from_slice(values);
+        self.write(&data)
+    }
+
+    fn smbus_process_block(&mut self, _reg : u8, _values : &[u8]) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "SMBus block process call"))
+    }
+}
+
+fn input(ch : Channel, fsr : FullScaleRange) -> AdcInput {
+    AdcInput { ch, fsr, dr : DataRate::DataRate860PerSec, divider : 1.0 }
+}
+
+#[test]
+fn scan_shared_with_air_quality() {
+    let model = Model::with_codes([0, 0, 0, 0, 1000, 2000, 3000, 4000]);
+    let adc = Ads1115::new(model.clone()).shared();
+    let mut scan = AdcScan::new(adc.clone(), AdcScanConf {
+        inputs     : vec![input(Channel::ChRelAn3, FullScaleRange::Fsr4p096), input(Channel::ChRelAn1, FullScaleRange::Fsr4p096)],
+        comparator : None,
+        alert_gpio : None,
+    }).unwrap();
+    let mut airq = AirQuality::new(adc, input(Channel::ChRelAn0, FullScaleRange::Fsr6p144));
+
+    // Scanned in channel order, one conversion each
+    let ms = scan.measure().unwrap();
+    let quantities : Vec<_> = ms.iter().map(|m| m.quantity).collect();
+    assert_eq!(quantities, [Quantity::AdcAn1, Quantity::AdcAn3]);
+    assert!(ms[0].value < ms[1].value);
+    assert!(airq.get_voltage().unwrap() > 0.0);
+    assert_eq!(model.conversions(), 3);
+    // Mux AIN0 single-ended was the last conversion
+    assert_eq!((model.reg(1) >> 12) & 0x07, 4);
+}
+
+#[test]
+fn channels() {
+    assert_eq!(Channel::ChDiffAn1An3.quantity(), Quantity::AdcAn1An3);
+    assert_eq!(Channel::ChRelAn2.quantity(), Quantity::AdcAn2);
+    assert!(Channel::ChDiffAn2An3.is_differential());
+    assert!(!Channel::ChRelAn0.is_differential());
+}
+
+// ALERT/RDY read through a GPIO sysfs `value` file
+struct Pin(std::path::PathBuf);
+
+impl Pin {
+    fn new(name : &str) -> Pin {
+        let pin = Pin(std::env::temp_dir().join(format!("rpiapptmon-test-{}-{}", name, std::process::id())));
+        pin.set(true);
+        pin
+    }
+
+    fn set(&self, high : bool) {
+        std::fs::write(&self.0, if high { "1\n" } else { "0\n" }).unwrap();
+    }
+
+    fn alert_pin(&self) -> AlertPin {
+        AlertPin::new(&self.0)
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn window(low : f32, high : f32) -> Comparator {
+    Comparator {
+        input       : input(Channel::ChRelAn0, FullScaleRange::Fsr4p096),
+        mode        : CompMode::Window,
+        low,
+        high,
+        queue       : CompQueueMode::CompQueue2,
+        latching    : true,
+        active_high : false,
+    }
+}
+
+#[test]
+fn comparator() {
+    let model = Model::with_codes([0, 0, 0, 0, 1000, 2000, 3000, 4000]);
+    let pin = Pin::new("alert");
+    let mut adc = Ads1115::new(model.clone());
+    adc.set_comparator(Some(window(0.5, 1.0)), Some(pin.alert_pin())).unwrap();
+    // Thresholds in codes, continuous conversions of the comparator input,
+    // window, latching, active low, asserted after 2 conversions
+    assert_eq!((model.reg(2), model.reg(3)), (4000, 8000));
+    assert_eq!(model.reg(1) & 0x7100, 0x4000);
+    assert_eq!(model.reg(1) & 0x001F, 0x0015);
+    assert!(!adc.take_alert().unwrap());
+
+    // An alert raised while another input is read is not lost, and the
+    // comparator input is watched again afterwards
+    pin.set(false);
+    adc.read_voltage(&input(Channel::ChRelAn2, FullScaleRange::Fsr4p096)).unwrap();
+    pin.set(true);
+    assert_eq!(model.reg(1) & 0x7100, 0x4000);
+    assert!(adc.take_alert().unwrap());
+    assert!(!adc.take_alert().unwrap());
+
+    // Back to single-shot, comparator disabled
+    adc.set_comparator(None, None).unwrap();
+    assert_eq!(model.reg(1) & 0x0103, 0x0103);
+}
+
+#[test]
+fn alert_needs_a_pin() {
+    let model = Model::with_codes([0; 8]);
+    let adc = Ads1115::new(model).shared();
+    let mut scan = AdcScan::new(adc, AdcScanConf {
+        inputs     : vec![input(Channel::ChRelAn1, FullScaleRange::Fsr4p096)],
+        comparator : Some(window(0.5, 1.0)),
+        alert_gpio : None,
+    }).unwrap();
+    let quantities : Vec<_> = scan.measure().unwrap().iter().map(|m| m.quantity).collect();
+    assert_eq!(quantities, [Quantity::AdcAn1]);
+}
+
+#[test]
+fn signed_codes_and_divider() {
+    let model = Model::with_codes([-1000, 0, 0, 0, 32767, 0, 0, 0]);
+    let mut adc = Ads1115::new(model);
+    let diff = input(Channel::ChDiffAn0An1, FullScaleRange::Fsr2p048);
+    assert_eq!(adc.read_raw(&diff).unwrap(), -1000);
+    // 62.5 µV per code at +-2.048 V
+    let v = adc.read_voltage(&diff).unwrap();
+    assert!((v + 0.0625).abs() < 1e-6, "{}", v);
+    // Full scale
+    let v = adc.read_voltage(&input(Channel::ChRelAn0, FullScaleRange::Fsr4p096)).unwrap();
+    assert!((v - 4.096).abs() < 1e-3, "{}", v);
+    let v = adc.read_voltage(&AdcInput { divider : 2.0, ..input(Channel::ChRelAn0, FullScaleRange::Fsr6p144) }).unwrap();
+    assert!((v - 12.288).abs() < 1e-2, "{}", v);
+}
+
+#[test]
+fn thresholds_through_the_divider() {
+    let model = Model::with_codes([0; 8]);
+    let mut adc = Ads1115::new(model.clone());
+    let mut comp = window(1.0, 2.0);
+    comp.input.divider = 2.0;
+    adc.set_comparator(Some(comp), None).unwrap();
+    assert_eq!((model.reg(2), model.reg(3)), (4000, 8000));
+}
@@ -0,0 +1,103 @@
+use rpiapptmon::error::Error;
+use rpiapptmon::sensors::mock::MockI2CDevice;
+use rpiapptmon::sensors::press_temp::*;
+use rpiapptmon::sensors::Sensor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Calibration, UT and UP of the BMP180 datasheet example (section 3.5)
+const COEFFS : [i16; 11] = [408, -72, -14383, 32741, 32757, 23153, 6190, 4, -32768, -8711, 2868];
+const UT     : u16 = 27898;
+const UP     : u32 = 23843;
+
+fn datasheet_device() -> MockI2CDevice {
+    let dev = MockI2CDevice::new();
+    let coeffs : Vec<u8> = COEFFS.iter().flat_map(|c| c.to_be_bytes().to_vec()).collect();
+    dev.set_regs(0xAA, &coeffs);
+    dev.set_reg(0xD0, 0x55);
+    dev
+}
+
+// Conversions complete as soon as they are started
+fn convert(dev : &MockI2CDevice) {
+    dev.on_write(0xF4, |r| {
+        let cmd = r[0xF4];
+        r[0xF4] &= !0x20;
+        if cmd == 0x2E {
+            r[0xF6..0xF8].copy_from_slice(&UT.to_be_bytes());
+        } else {
+            r[0xF6..0xF9].copy_from_slice(&(UP << 8).to_be_bytes()[1..]);
+        }
+    });
+}
+
+#[test]
+fn datasheet_example() {
+    let dev = datasheet_device();
+    convert(&dev);
+    let mut bmp = BMP180::new(dev.clone()).unwrap();
+    bmp.self_test().unwrap();
+    assert_eq!(bmp.read_temperature().unwrap(), 15.0);
+    assert_eq!(bmp.read_pressure(Oss::Oss1).unwrap(), 69964);
+}
+
+#[test]
+fn one_temperature_conversion() {
+    let dev = datasheet_device();
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let seen = commands.clone();
+    dev.on_write(0xF4, move |r| {
+        let cmd = r[0xF4];
+        seen.lock().unwrap().push(cmd);
+        r[0xF4] &= !0x20;
+        if cmd == 0x2E {
+            r[0xF6..0xF8].copy_from_slice(&UT.to_be_bytes());
+        } else {
+            r[0xF6..0xF9].copy_from_slice(&(UP << 8).to_be_bytes()[1..]);
+        }
+    });
+    let mut bmp = BMP180::new(dev).unwrap();
+    bmp.self_test().unwrap();
+    commands.lock().unwrap().clear();
+    assert_eq!(bmp.read(Oss::Oss1).unwrap(), (15.0, 69964));
+    bmp.measure().unwrap();
+    // UT then UP, once each per read
+    assert_eq!(*commands.lock().unwrap(), [0x2E, 0x34, 0x2E, 0x34]);
+}
+
+#[test]
+fn offline_device() {
+    let dev = datasheet_device();
+    convert(&dev);
+    let mut bmp = BMP180::new(dev.clone()).unwrap();
+    dev.set_offline(true);
+    assert!(matches!(bmp.read_temperature(), Err(Error::Bus { sensor : "bmp180", .. })));
+    dev.set_offline(false);
+    assert_eq!(bmp.read_temperature().unwrap(), 15.0);
+}
+
+#[test]
+fn conversion_timeout() {
+    // The busy bit is never cleared
+    let mut bmp = BMP180::new(datasheet_device()).unwrap();
+    let started = Instant::now();
+    assert!(matches!(bmp.read_temperature(), Err(Error::Timeout { sensor : "bmp180", reg : 0xF4, .. })));
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn sea_level_and_altitude() {
+    let qnh = sea_level_pressure(95000.0, 500.0);
+    assert!((qnh - 100_835.0).abs() < 1.0, "{}", qnh);
+    assert!((altitude(95000.0, qnh) - 500.0).abs() < 0.5);
+
+    let dev = datasheet_device();
+    convert(&dev);
+    let mut bmp = BMP180::new(dev).unwrap();
+    bmp.set_pressure_ref(PressureRef { station_altitude : Some(500.0), qnh : Some(101325.0) });
+    let m = bmp.measure().unwrap();
+    let quantities : Vec<_> = m.iter().map(|m| m.quantity).collect();
+    assert_eq!(quantities, PRESS_TEMP_QUANTITIES);
+    assert_eq!(m[2].value, sea_level_pressure(69964.0, 500.0) as f64);
+    assert_eq!(m[3].value, altitude(69964.0, 101325.0) as f64);
+}
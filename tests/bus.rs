@@ -0,0 +1,9 @@
+use rpiapptmon::sensors::bus::Bus;
+use std::io;
+
+#[test]
+fn missing_adapter() {
+    let e = Bus::open("/dev/i2c-missing").err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    assert!(e.to_string().starts_with("Cannot open I2C bus /dev/i2c-missing : "), "{}", e);
+}
@@ -0,0 +1,41 @@
+use chrono::prelude::*;
+use rpiapptmon::comfort::*;
+use rpiapptmon::sampling::{Derive, Snapshot};
+use rpiapptmon::sensors::{Measurement, Quantity, Unit};
+
+#[test]
+fn formulas() {
+    let dp = dew_point(20.0, 50.0);
+    assert!((dp - 9.26).abs() < 0.05, "{}", dp);
+    assert!((relative_humidity(20.0, dp) - 50.0).abs() < 1e-9);
+    let ah = absolute_humidity(20.0, dp);
+    assert!((ah - 8.65).abs() < 0.1, "{}", ah);
+    // NWS table : 90 *F at 70 % feels like 106 *F
+    let hi = heat_index((90.0 - 32.0) / 1.8, 70.0) * 1.8 + 32.0;
+    assert!((hi - 106.0).abs() < 1.0, "{}", hi);
+    // Environment Canada table : 30 *C with a 15 *C dew point gives 34
+    let h = humidex(30.0, 15.0);
+    assert!((h - 34.0).abs() < 0.6, "{}", h);
+    assert_eq!((mould_risk(69.9), mould_risk(70.0), mould_risk(80.0)), (0, 1, 2));
+}
+
+#[test]
+fn humidity_sensor_temperature() {
+    // The bare temperature key belongs to the BMP180, the humidity was
+    // measured at the HTS221 temperature
+    let snap = Snapshot::new(Utc::now(), vec![
+        Measurement::new("bmp180", Quantity::Temperature, Unit::DegC,    20.0),
+        Measurement::new("hts221", Quantity::Humidity,    Unit::Percent, 40.0),
+        Measurement::new("hts221", Quantity::Temperature, Unit::DegC,    25.0),
+    ]);
+    let mut comfort = Comfort::new();
+    let out = comfort.derive(&snap);
+    let quantities : Vec<_> = out.iter().map(|m| m.quantity).collect();
+    assert_eq!(quantities, COMFORT_QUANTITIES);
+    let dp = dew_point(25.0, 40.0);
+    assert_eq!(out[0].value, dp);
+    assert_eq!(out[1].value, absolute_humidity(20.0, dp));
+    assert_eq!(out[3].value, humidex(20.0, dp));
+    // Same readings, nothing new
+    assert!(comfort.derive(&snap).is_empty());
+}
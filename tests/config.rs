@@ -0,0 +1,158 @@
+use rpiapptmon::config::*;
+use rpiapptmon::sensors::imu::GyroOdr;
+use rpiapptmon::sensors::light::{LightMode, WaitTime};
+use std::time::Duration;
+
+fn invalid_field(text : &str) -> String {
+    match Config::parse(text) {
+        Err(ConfigError::Invalid { field, .. }) => field,
+        Err(e) => panic!("{}", e),
+        Ok(_) => panic!("{:?} accepted", text),
+    }
+}
+
+#[test]
+fn example_file() {
+    let c = Config::load("rpiapptmon.example.toml").unwrap();
+    assert!(c.bmp180.is_some());
+    assert!(c.lps25h.is_none());
+    assert!(c.lsm9ds1.is_none());
+}
+
+#[test]
+fn defaults() {
+    let c = Config::parse("").unwrap();
+    assert_eq!(c.general.i2c_bus, "/dev/i2c-1");
+    assert!(c.bmp180.is_none());
+    let c = Config::parse("[sensors.lsm9ds1]\n").unwrap();
+    assert!(c.lsm9ds1.unwrap().gyro_odr == GyroOdr::Odr119Hz);
+}
+
+#[test]
+fn invalid_values() {
+    assert_eq!(invalid_field("[sensors.bmp180]\noss = 3\n"), "sensors.bmp180.oss");
+    assert_eq!(invalid_field("[sensors.mics5524]\nchannel = \"an7\"\n"), "sensors.mics5524.channel");
+    assert_eq!(invalid_field("[sensors.lsm9ds1]\ngyro_odr = 100\n"), "sensors.lsm9ds1.gyro_odr");
+}
+
+#[test]
+fn unknown_keys() {
+    assert!(matches!(Config::parse("[sensors.bmp18]\n"), Err(ConfigError::Parse(..))));
+    assert!(matches!(Config::parse("[general]\nfoo = 1\n"), Err(ConfigError::Parse(..))));
+}
+
+#[test]
+fn path_from_args() {
+    let args = |a : &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter();
+    assert_eq!(Config::path_from_args(args(&["rpiapptmon", "--config", "a.toml"])).unwrap().to_str(), Some("a.toml"));
+    assert!(matches!(Config::path_from_args(args(&["rpiapptmon", "--config"])), Err(ConfigError::Usage(_))));
+}
+
+#[test]
+fn web() {
+    let c = Config::load("rpiapptmon.example.toml").unwrap();
+    assert_eq!(c.web.unwrap().listen, "0.0.0.0:8000");
+    assert!(Config::parse("[web]\nenabled = false\n").unwrap().web.is_none());
+    assert_eq!(invalid_field("[web]\nlisten = \"foo\"\n"), "web.listen");
+}
+
+#[test]
+fn sinks() {
+    use rpiapptmon::sinks::{MqttMode, SinkConf};
+
+    let c = Config::load("rpiapptmon.example.toml").unwrap();
+    assert_eq!(c.telemetry.sinks.len(), 1);
+    assert!(matches!(c.telemetry.sinks[0].conf, SinkConf::ThingsBoard { .. }));
+
+    let c = Config::parse(concat!(
+        "[[telemetry.sink]]\ntype = \"mqtt\"\nhost = \"x\"\nmode = \"plain\"\n",
+        "[[telemetry.sink]]\ntype = \"jsonl\"\n",
+        "[[telemetry.sink]]\ntype = \"mqtt\"\nhost = \"y:1\"\naccess_token = \"t\"\n",
+    )).unwrap();
+    let names : Vec<&str> = c.telemetry.sinks.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["mqtt", "jsonl", "mqtt_2"]);
+    match c.telemetry.sinks[2].conf {
+        SinkConf::Mqtt(ref mc) => {
+            assert_eq!(mc.host, "y:1");
+            assert_eq!(mc.username.as_deref(), Some("t"));
+            assert_eq!(mc.mode, MqttMode::ThingsBoard);
+        },
+        ref conf => panic!("{:?}", conf),
+    }
+
+    assert!(Config::parse("[[telemetry.sink]]\ntype = \"thingsboard\"\naccess_token = \"\"\n").is_err());
+    assert!(Config::parse("[[telemetry.sink]]\ntype = \"mqtt\"\nhost = \"x\"\n").is_err());
+    assert!(Config::parse("[[telemetry.sink]]\ntype = \"foo\"\n").is_err());
+    assert!(Config::parse("[[telemetry.sink]]\ntype = \"influxdb\"\nurl = \"http://x\"\ndatabase = \"a b\"\n").is_err());
+}
+
+#[test]
+fn intervals() {
+    let cfg = Config::load("rpiapptmon.example.toml").unwrap();
+    assert_eq!(cfg.interval("tcs34725"), Duration::from_secs(5));
+    assert_eq!(cfg.interval("bmp180"), Duration::from_secs(30));
+    let cfg = Config::parse("[general]\nsample_interval = 10\n[sensors.bmp180]\ninterval = 2").unwrap();
+    assert_eq!(cfg.interval("bmp180"), Duration::from_secs(2));
+    assert_eq!(cfg.interval("lps25h"), Duration::from_secs(10));
+}
+
+#[test]
+fn pressure_ref() {
+    let cfg = Config::parse("[general]\nstation_altitude = 420.0\nqnh = 1020\n").unwrap();
+    assert_eq!(cfg.general.pressure_ref.station_altitude, Some(420.0));
+    assert_eq!(cfg.general.pressure_ref.qnh, Some(102000.0));
+    assert_eq!(invalid_field("[general]\nqnh = 10\n"), "general.qnh");
+}
+
+#[test]
+fn matrix() {
+    assert!(Config::parse("[matrix]\nenabled = true\nrotation = 90\n").unwrap().matrix.is_some());
+    assert!(Config::parse("").unwrap().matrix.is_none());
+    assert_eq!(invalid_field("[matrix]\nrotation = 45\n"), "matrix.rotation");
+}
+
+#[test]
+fn light_switch() {
+    let cfg = Config::parse("[sensors.tcs34725]\nswitch_on = 100\nswitch_off = 20\n").unwrap();
+    assert!(cfg.tcs34725.unwrap().watch.is_some());
+    assert_eq!(invalid_field("[sensors.tcs34725]\nswitch_on = 100\nswitch_off = 200\n"), "sensors.tcs34725.switch_off");
+    assert_eq!(invalid_field("[sensors.tcs34725]\nswitch_on = 100\nauto_range = true\n"), "sensors.tcs34725.auto_range");
+    assert_eq!(invalid_field("[sensors.tcs34725]\nswitch_on = 100\npersistence = 4\n"), "sensors.tcs34725.persistence");
+}
+
+#[test]
+fn light_mode() {
+    let cfg = Config::parse("[sensors.tcs34725]\nmode = \"continuous\"\nwait_ms = 1000\n").unwrap();
+    assert_eq!(cfg.tcs34725.unwrap().mode, LightMode::LowPower(WaitTime::from_ms(1000.0)));
+    let cfg = Config::parse("[sensors.tcs34725]\nmode = \"continuous\"\n").unwrap();
+    assert_eq!(cfg.tcs34725.unwrap().mode, LightMode::Continuous);
+    assert_eq!(invalid_field("[sensors.tcs34725]\nwait_ms = 1000\n"), "sensors.tcs34725.wait_ms");
+    assert_eq!(invalid_field("[sensors.tcs34725]\nmode = \"continuous\"\nwait_ms = 8000\n"), "sensors.tcs34725.wait_ms");
+    assert_eq!(invalid_field("[sensors.tcs34725]\nmode = \"burst\"\n"), "sensors.tcs34725.mode");
+}
+
+#[test]
+fn adc_inputs() {
+    let cfg = Config::parse("[sensors.ads1115]\n[[sensors.ads1115.input]]\nchannel = \"an1\"\n[[sensors.ads1115.input]]\nchannel = \"an0-an1\"\nfsr = 0.256\n").unwrap();
+    assert_eq!(cfg.ads1115.unwrap().inputs.len(), 2);
+    assert_eq!(invalid_field("[sensors.ads1115]\n"), "sensors.ads1115.input");
+    assert_eq!(invalid_field("[sensors.ads1115]\n[[sensors.ads1115.input]]\nchannel = \"an1\"\n[[sensors.ads1115.input]]\nchannel = \"an1\"\n"), "sensors.ads1115.input[1].channel");
+}
+
+#[test]
+fn adc_alert() {
+    let cfg = Config::parse("[sensors.ads1115.alert]\nchannel = \"an0\"\nlow = 1.0\nhigh = 2.0\nmode = \"window\"\nqueue = 4\ngpio = 17\n").unwrap();
+    let adc = cfg.ads1115.unwrap();
+    assert!(adc.inputs.is_empty());
+    assert_eq!(adc.alert_gpio, Some(17));
+    assert!(adc.comparator.unwrap().latching);
+    assert_eq!(invalid_field("[sensors.ads1115.alert]\nchannel = \"an0\"\nlow = 2.0\nhigh = 1.0\n"), "sensors.ads1115.alert.low");
+    assert_eq!(invalid_field("[sensors.ads1115.alert]\nchannel = \"an0\"\nlow = 1.0\nhigh = 2.0\nqueue = 3\n"), "sensors.ads1115.alert.queue");
+}
+
+#[test]
+fn adc_divider() {
+    let cfg = Config::parse("[sensors.mics5524]\ndivider = 3\n").unwrap();
+    assert_eq!(cfg.mics5524.unwrap().divider, 3.0);
+    assert_eq!(invalid_field("[sensors.mics5524]\ndivider = 0\n"), "sensors.mics5524.divider");
+}
@@ -0,0 +1,84 @@
+use chrono::prelude::*;
+use rpiapptmon::forecast::*;
+use rpiapptmon::hmi::icons;
+use rpiapptmon::sampling::{Derive, Snapshot};
+use rpiapptmon::sensors::{Measurement, Quantity, Unit};
+use rpiapptmon::storage::{Retention, Store};
+
+fn at(s : i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_600_000_000 + s, 0).unwrap()
+}
+
+fn snapshot(s : i64, pa : f64) -> Snapshot {
+    let mut m = Measurement::new("bmp180", Quantity::SeaLevelPressure, Unit::Pascal, pa);
+    m.timestamp = at(s);
+    Snapshot::new(at(s), vec![m])
+}
+
+#[test]
+fn rising() {
+    let mut f = Forecaster::new();
+    let mut out = Vec::new();
+    // 1 hPa/h, every 15 min for 3 h
+    for i in 0..=12 {
+        out = f.derive(&snapshot(i * 900, 101000.0 + i as f64 * 25.0));
+        assert_eq!(out.is_empty(), i < 4, "after {} readings", i + 1);
+    }
+    assert!((out[0].value - 1.0).abs() < 1e-9, "{}", out[0].value);
+    assert_eq!(out[1].value, Tendency::Rising as i8 as f64);
+    assert_eq!(out[2].value, zambretti(1013.0, Tendency::Rising) as f64);
+    // Nothing new, nothing derived
+    assert!(f.derive(&snapshot(12 * 900, 101300.0)).is_empty());
+}
+
+#[test]
+fn falling() {
+    let mut f = Forecaster::new();
+    for i in 0..=12 {
+        f.push(at(i * 900), 100000.0 - i as f64 * 50.0);
+    }
+    let forecast = f.forecast().unwrap();
+    assert!((forecast.rate + 2.0).abs() < 1e-9);
+    assert_eq!(forecast.tendency, Tendency::Falling);
+    assert_eq!(forecast.code, zambretti(994.0, Tendency::Falling));
+    assert_eq!(forecast.outlook(), Outlook::Rain);
+    assert_eq!(describe(forecast.code), Some((forecast.text(), forecast.outlook())));
+}
+
+#[test]
+fn one_icon_per_outlook() {
+    let frames : Vec<_> = [Outlook::Fine, Outlook::Fair, Outlook::Changeable, Outlook::Rain, Outlook::Stormy]
+        .iter().map(|o| icons::outlook(*o)).collect();
+    for (i, frame) in frames.iter().enumerate() {
+        assert!(frames[i + 1..].iter().all(|other| other[..] != frame[..]));
+    }
+}
+
+#[test]
+fn zambretti_codes() {
+    assert_eq!(zambretti(1030.0, Tendency::Steady), 10);
+    assert_eq!(zambretti(960.0, Tendency::Falling), 9);
+    assert_eq!(zambretti(1050.0, Tendency::Rising), 20);
+    assert_eq!(describe(0), None);
+    assert_eq!(describe(33), None);
+}
+
+#[test]
+fn seeded_from_the_store() {
+    let dir = std::env::temp_dir().join(format!("rpiapptmon-test-forecast-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut store = Store::open(&dir, Retention { raw : None, minute : None, hour : None, day : None }).unwrap();
+    // 2 h rising by 1 hPa/h, and older readings out of the window
+    store.append("pressure_sea_level", at(-5 * 3600), 90000.0).unwrap();
+    for i in 0..=8 {
+        store.append("pressure_sea_level", at(i * 900), 101000.0 + i as f64 * 25.0).unwrap();
+        store.append("pressure", at(i * 900), 95000.0).unwrap();
+    }
+
+    let mut f = Forecaster::new();
+    f.seed(&store, at(2 * 3600 + 60)).unwrap();
+    let out = f.derive(&snapshot(2 * 3600 + 60, 101200.0));
+    assert_eq!(out.len(), 3);
+    assert!((out[0].value - 1.0).abs() < 0.05, "{}", out[0].value);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,65 @@
+use rpiapptmon::error::Error;
+use rpiapptmon::sensors::humidity::*;
+use rpiapptmon::sensors::mock::MockI2CDevice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Calibration points (20 *C, 20 %rH) and (40 *C, 40 %rH), at outputs
+// T0_OUT = -100, T1_OUT = 900, H0_T0_OUT = 0, H1_T0_OUT = 1000
+fn device() -> MockI2CDevice {
+    let dev = MockI2CDevice::with_reg_mask(0x7F);
+    dev.set_reg(0x0F, 0xBC);
+    dev.set_regs(0x30, &[40, 80, 160, 64, 0, 0x04]);
+    dev.set_regs(0x36, &0i16.to_le_bytes());
+    dev.set_regs(0x3A, &1000i16.to_le_bytes());
+    dev.set_regs(0x3C, &(-100i16).to_le_bytes());
+    dev.set_regs(0x3E, &900i16.to_le_bytes());
+    dev
+}
+
+fn hts221(dev : &MockI2CDevice) -> HumiditySensor<MockI2CDevice> {
+    HumiditySensor::new(dev.clone(), HumAvgCfg::AvgSmplx4, TempAvgCfg::AvgSmplx4, OutDataRate::SingleShot,
+                        ctrl1_msks::PWR_UP | ctrl1_msks::BDU_ENA, 0, 0).unwrap()
+}
+
+#[test]
+fn calibration() {
+    let hts = hts221(&device());
+    assert_eq!(*hts.calibration(), Calibration {
+        t0_deg_c  : 20.0,
+        t1_deg_c  : 40.0,
+        t0_out    : -100,
+        t1_out    : 900,
+        h0_rh     : 20.0,
+        h1_rh     : 40.0,
+        h0_t0_out : 0,
+        h1_t0_out : 1000,
+    });
+}
+
+#[test]
+fn one_conversion() {
+    let dev = device();
+    let conversions = Arc::new(AtomicUsize::new(0));
+    let counter = conversions.clone();
+    dev.on_write(0x21, move |r| {
+        if r[0x21] & 0x01 != 0 {
+            counter.fetch_add(1, Ordering::SeqCst);
+            r[0x21] &= !0x01;
+            r[0x27] = 0x03;
+            r[0x28..0x2A].copy_from_slice(&500i16.to_le_bytes());
+            r[0x2A..0x2C].copy_from_slice(&400i16.to_le_bytes());
+        }
+    });
+    let mut hts = hts221(&dev);
+    assert_eq!(hts.read().unwrap(), (30.0, 30.0));
+    assert_eq!(conversions.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn flat_calibration() {
+    let dev = device();
+    let mut hts = hts221(&dev);
+    dev.set_regs(0x3E, &(-100i16).to_le_bytes());
+    assert!(matches!(hts.reload_calibration(), Err(Error::Calibration { sensor : "hts221", .. })));
+}
@@ -0,0 +1,64 @@
+use rpiapptmon::error::Error;
+use rpiapptmon::sensors::mock::MockI2CDevice;
+use rpiapptmon::sensors::press_temp::*;
+use std::time::{Duration, Instant};
+
+fn conf() -> Lps25hConf {
+    Lps25hConf {
+        odr       : Lps25hOdr::OneShot,
+        tavg      : Lps25hTempAvg::AvgSmplx16,
+        pavg      : Lps25hPressAvg::AvgSmplx32,
+        fifo_mean : Some(FifoMeanSmpl::Mean8),
+    }
+}
+
+fn device() -> MockI2CDevice {
+    let dev = MockI2CDevice::with_reg_mask(0x7F);
+    dev.set_reg(0x0F, 0xBD);
+    dev.set_reg(0x27, 0x03);
+    dev
+}
+
+#[test]
+fn read_one_shot() {
+    let dev = device();
+    // 1013.25 hPa * 4096 LSB/hPa, 25 *C = 42.5 *C - 8400 / 480
+    dev.set_regs(0x28, &[0x00, 0x54, 0x3F]);
+    dev.set_regs(0x2B, &(-8400i16).to_le_bytes());
+    let mut lps = LPS25H::new(dev.clone(), conf()).unwrap();
+    assert_eq!(lps.read_pressure().unwrap(), 101325.0);
+    assert_eq!(lps.read_temperature().unwrap(), 25.0);
+    // FIFO mean mode, 8 samples
+    assert_eq!(dev.reg(0x2E), 0xC7);
+    // ONE_SHOT written along FIFO_EN
+    assert_eq!(dev.reg(0x21), 0x41);
+}
+
+#[test]
+fn reference_and_offset() {
+    let dev = device();
+    let mut lps = LPS25H::new(dev.clone(), conf()).unwrap();
+    lps.set_reference_pressure(101325.0).unwrap();
+    assert_eq!(lps.get_reference_pressure().unwrap(), 101325.0);
+    lps.set_pressure_offset(-50.0).unwrap();
+    assert_eq!(lps.get_pressure_offset().unwrap(), -50.0);
+    lps.set_differential(true).unwrap();
+    assert_ne!(dev.reg(0x20) & 0x08, 0);
+}
+
+#[test]
+fn wrong_id() {
+    let dev = device();
+    dev.set_reg(0x0F, 0xBC);
+    assert!(LPS25H::new(dev, conf()).is_err());
+}
+
+#[test]
+fn data_never_ready() {
+    let dev = device();
+    dev.set_reg(0x27, 0x00);
+    let mut lps = LPS25H::new(dev, Lps25hConf { odr : Lps25hOdr::DataRate25Hz, ..conf() }).unwrap();
+    let started = Instant::now();
+    assert!(matches!(lps.read_pressure(), Err(Error::Timeout { sensor : "lps25h", reg : 0x27, .. })));
+    assert!(started.elapsed() < Duration::from_millis(300));
+}
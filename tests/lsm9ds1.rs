@@ -0,0 +1,78 @@
+use rpiapptmon::sensors::mock::MockI2CDevice;
+use rpiapptmon::sensors::Sensor;
+use rpiapptmon::sensors::imu::*;
+
+fn conf() -> ImuConf {
+    ImuConf {
+        gyro_odr  : GyroOdr::Odr119Hz,
+        gyro_fsr  : GyroFsr::Fsr245Dps,
+        accel_odr : AccelOdr::Odr119Hz,
+        accel_fsr : AccelFsr::Fsr2G,
+        mag_odr   : MagOdr::Odr10Hz,
+        mag_fsr   : MagFsr::Fsr4Gauss,
+        mag_mode  : MagMode::Continuous,
+        mag_perf  : MagPerf::HighPerf,
+    }
+}
+
+// Accelerometer/gyroscope and magnetometer, all data ready
+fn devices() -> (MockI2CDevice, MockI2CDevice) {
+    let ag = MockI2CDevice::new();
+    let mag = MockI2CDevice::with_reg_mask(0x7F);
+    ag.set_reg(0x0F, 0x68);
+    mag.set_reg(0x0F, 0x3D);
+    ag.set_reg(0x17, 0x07);
+    mag.set_reg(0x27, 0x08);
+    (ag, mag)
+}
+
+#[test]
+fn read_all() {
+    let (ag, mag) = devices();
+    // 1 g on Z, 16384 * 0.061 mg
+    ag.set_regs(0x28, &[0x00, 0x00, 0x00, 0x00, 0x00, 0x40]);
+    // 8 * 8.75 mdps on X
+    ag.set_regs(0x18, &[0x08, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    ag.set_regs(0x15, &[0x10, 0x00]);
+    // +-10000 * 0.14 mgauss on X and Z
+    mag.set_regs(0x28, &[0x10, 0x27, 0x00, 0x00, 0xF0, 0xD8]);
+
+    let mut imu = LSM9DS1::new(ag.clone(), mag.clone(), conf()).unwrap();
+    let a = imu.read_accel().unwrap();
+    assert!((a.z - 0.999).abs() < 1e-3, "{:?}", a);
+    let g = imu.read_gyro().unwrap();
+    assert!((g.x - 0.07).abs() < 1e-4, "{:?}", g);
+    let m = imu.read_mag().unwrap();
+    assert!((m.x - 1.4).abs() < 1e-3 && (m.z + 1.4).abs() < 1e-3, "{:?}", m);
+    assert_eq!(imu.read_temperature().unwrap(), 26.0);
+}
+
+#[test]
+fn configuration() {
+    let (ag, mag) = devices();
+    LSM9DS1::new(ag.clone(), mag.clone(), conf()).unwrap();
+    // CTRL_REG1_G : 119 Hz, 245 dps
+    assert_eq!(ag.reg(0x10), 0x60);
+    // CTRL_REG6_XL : 119 Hz, 2 g
+    assert_eq!(ag.reg(0x20), 0x60);
+    // CTRL_REG1_M : high performance, 10 Hz
+    assert_eq!(mag.reg(0x20), 0x50);
+    assert_eq!(mag.reg(0x22), 0x00);
+}
+
+#[test]
+fn wrong_id() {
+    let (ag, mag) = devices();
+    mag.set_reg(0x0F, 0x00);
+    assert!(LSM9DS1::new(ag, mag, conf()).is_err());
+}
+
+#[test]
+fn gyro_powered_down() {
+    let (ag, mag) = devices();
+    // No gyroscope data ready
+    ag.set_reg(0x17, 0x05);
+    let mut imu = LSM9DS1::new(ag, mag, ImuConf { gyro_odr : GyroOdr::PowerDown, ..conf() }).unwrap();
+    let keys : Vec<_> = imu.measure().unwrap().iter().map(|m| m.quantity.key()).collect();
+    assert_eq!(keys, vec!["accel_x", "accel_y", "accel_z", "mag_x", "mag_y", "mag_z"]);
+}
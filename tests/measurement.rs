@@ -0,0 +1,32 @@
+use rpiapptmon::sensors::measurement::{telemetry_key, telemetry_keys, to_json};
+use rpiapptmon::sensors::{Measurement, Quantity, Unit};
+use serde_json::json;
+
+fn readings() -> Vec<Measurement> {
+    vec![
+        Measurement::new("bmp180", Quantity::Temperature, Unit::DegC,   21.5),
+        Measurement::new("bmp180", Quantity::Pressure,    Unit::Pascal, 101325),
+        Measurement::new("lps25h", Quantity::Pressure,    Unit::Pascal, 101300),
+        Measurement::new("hts221", Quantity::Humidity,    Unit::Percent, 40),
+    ]
+}
+
+#[test]
+fn first_sensor_owns_the_key() {
+    assert_eq!(telemetry_keys(&readings()), vec!["temperature", "pressure", "pressure_lps25h", "humidity"]);
+    assert_eq!(to_json(&readings()), json!({
+        "temperature"     : 21.5,
+        "pressure"        : 101325.0,
+        "pressure_lps25h" : 101300.0,
+        "humidity"        : 40.0,
+    }));
+}
+
+#[test]
+fn owner_given_up_front() {
+    let owners = vec![(Quantity::Pressure, "bmp180".to_owned())];
+    let lps = &readings()[2];
+    // The key stays the same when the owner has no reading
+    assert_eq!(telemetry_key(&owners, lps), "pressure_lps25h");
+    assert_eq!(telemetry_keys(std::slice::from_ref(lps)), vec!["pressure"]);
+}
@@ -0,0 +1,54 @@
+use rpiapptmon::outbox::*;
+use serde_json::json;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+#[test]
+fn bounded_queue() {
+    let dir = std::env::temp_dir().join(format!("rpiapptmon-test-outbox-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("queue.jsonl");
+
+    let mut outbox = Outbox::open(&path, 3).unwrap();
+    for i in 0..3 {
+        assert_eq!(outbox.push(&json!({ "ts" : i })).unwrap(), 0);
+    }
+    // The oldest entry is dropped when full
+    assert_eq!(outbox.push(&json!({ "ts" : 3 })).unwrap(), 1);
+    assert_eq!(outbox.front(), Some("{\"ts\":1}"));
+    outbox.pop();
+    outbox.commit().unwrap();
+    outbox.push(&json!({ "ts" : 4 })).unwrap();
+
+    // A line cut by a power loss is skipped on reopening
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"ts\":").unwrap();
+    let mut outbox = Outbox::open(&path, 3).unwrap();
+    assert_eq!(outbox.len(), 3);
+    let mut sent = Vec::new();
+    while let Some(front) = outbox.front().map(str::to_owned) {
+        sent.push(front);
+        outbox.pop();
+    }
+    assert_eq!(sent, vec!["{\"ts\":2}", "{\"ts\":3}", "{\"ts\":4}"]);
+    outbox.commit().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn backoff() {
+    let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(600));
+    let now = Instant::now();
+    assert!(backoff.ready(now));
+    let delays : Vec<u64> = (0..7).map(|_| backoff.failed(now).as_secs()).collect();
+    assert_eq!(delays, vec![30, 60, 120, 240, 480, 600, 600]);
+    assert!(!backoff.ready(now));
+    assert!(backoff.ready(now + Duration::from_secs(600)));
+    // No overflow after many failures
+    for _ in 0..100 {
+        backoff.failed(now);
+    }
+    backoff.succeeded();
+    assert!(backoff.ready(now));
+    assert_eq!(backoff.failures(), 0);
+}
@@ -0,0 +1,121 @@
+use chrono::prelude::*;
+use rpiapptmon::error::Error;
+use rpiapptmon::sampling::{Aggregator, Deferred, Snapshot, Status};
+use rpiapptmon::sensors::mock::MockI2CDevice;
+use rpiapptmon::sensors::press_temp::*;
+use rpiapptmon::sensors::{Measurement, Quantity, Sensor, Unit};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+// BMP180 with the calibration of its datasheet example, converting at once
+fn bmp180_device() -> MockI2CDevice {
+    let dev = MockI2CDevice::new();
+    let coeffs : [i16; 11] = [408, -72, -14383, 32741, 32757, 23153, 6190, 4, -32768, -8711, 2868];
+    let coeffs : Vec<u8> = coeffs.iter().flat_map(|c| c.to_be_bytes().to_vec()).collect();
+    dev.set_regs(0xAA, &coeffs);
+    dev.set_reg(0xD0, 0x55);
+    dev.on_write(0xF4, |r| {
+        let cmd = r[0xF4];
+        r[0xF4] &= !0x20;
+        if cmd == 0x2E {
+            r[0xF6..0xF8].copy_from_slice(&27898u16.to_be_bytes());
+        } else {
+            r[0xF6..0xF9].copy_from_slice(&(23843u32 << 8).to_be_bytes()[1..]);
+        }
+    });
+    dev
+}
+
+fn deferred_bmp180(dev : &MockI2CDevice) -> Box<dyn Sensor> {
+    let dev = dev.clone();
+    Box::new(Deferred::new(BMP180_ID_STR, &PRESS_TEMP_QUANTITIES, move || {
+        let mut bmp = BMP180::new(dev.clone())?;
+        bmp.self_test()?;
+        Ok(Box::new(bmp) as Box<dyn Sensor>)
+    }))
+}
+
+#[test]
+fn snapshot_keys() {
+    let snap = Snapshot::new(Utc::now(), vec![
+        Measurement::new("bmp180", Quantity::Pressure,    Unit::Pascal, 101325),
+        Measurement::new("lps25h", Quantity::Pressure,    Unit::Pascal, 101300),
+        Measurement::new("lps25h", Quantity::Temperature, Unit::DegC,   21.5),
+    ]);
+    assert_eq!(snap.iter().map(|(k, _)| k).collect::<Vec<_>>(), vec!["pressure", "pressure_lps25h", "temperature"]);
+    assert_eq!(snap.get("pressure_lps25h").unwrap().value, 101300.0);
+    assert!(snap.get("humidity").is_none());
+    assert_eq!(serde_json::Value::Object(snap.values()), json!({
+        "pressure"        : 101325.0,
+        "pressure_lps25h" : 101300.0,
+        "temperature"     : 21.5,
+    }));
+    assert!(snap.failed().is_empty());
+}
+
+#[test]
+fn sensor_comes_back() {
+    let dev = bmp180_device();
+    dev.set_reg(0xD0, 0x56);
+    let mut agg = Aggregator::new();
+    agg.spawn(deferred_bmp180(&dev), Duration::from_millis(50)).unwrap();
+
+    let result = agg.recv_until(Instant::now() + Duration::from_secs(1)).unwrap();
+    assert!(matches!(result, Err(Error::IdMismatch { .. })));
+    let (snap, _) = agg.take();
+    assert!(snap.is_empty());
+    assert_eq!(snap.failed(), ["bmp180"]);
+    assert_eq!(agg.health()[0].status, Status::Failing);
+
+    // Created again on the next cycle
+    dev.set_reg(0xD0, 0x55);
+    agg.recv_until(Instant::now() + Duration::from_secs(1)).unwrap().unwrap();
+    let (snap, _) = agg.take();
+    assert_eq!(snap.get("temperature").unwrap().value, 15.0);
+    assert_eq!(snap.get("pressure").unwrap().value, 69964.0);
+    assert!(snap.failed().is_empty());
+
+    let health = &agg.health()[0];
+    assert_eq!((health.status, health.reads, health.errors, health.consecutive_errors), (Status::Ok, 2, 1, 0));
+    assert!(health.last_error.is_some());
+}
+
+// Reports a constant pressure
+struct Fixed(&'static str, f64);
+
+impl Sensor for Fixed {
+    fn id(&self) -> &str {
+        self.0
+    }
+
+    fn quantities(&self) -> &'static [Quantity] {
+        &[Quantity::Pressure]
+    }
+
+    fn measure(&mut self) -> rpiapptmon::error::Result<Vec<Measurement>> {
+        Ok(vec![Measurement::new(self.0, Quantity::Pressure, Unit::Pascal, self.1)])
+    }
+}
+
+#[test]
+fn own_intervals() {
+    let mut agg = Aggregator::new();
+    agg.spawn(Box::new(Fixed("slow", 101300.0)), Duration::from_millis(400)).unwrap();
+    agg.spawn(Box::new(Fixed("fast", 101325.0)), Duration::from_millis(50)).unwrap();
+
+    let deadline = Instant::now() + Duration::from_millis(230);
+    while let Some(result) = agg.recv_until(deadline) {
+        result.unwrap();
+    }
+    let health = agg.health();
+    assert_eq!(health[0].reads, 1);
+    assert!((4..=6).contains(&health[1].reads), "{} reads", health[1].reads);
+
+    // The sensor spawned first keeps the bare key, whichever reported last
+    let (snap, errors) = agg.take();
+    assert!(errors.is_empty());
+    assert_eq!(snap.iter().map(|(k, _)| k).collect::<Vec<_>>(), vec!["pressure", "pressure_fast"]);
+    let (snap, _) = agg.take();
+    assert!(snap.is_empty());
+    assert_eq!(agg.current().get("pressure").unwrap().value, 101300.0);
+}
@@ -0,0 +1,338 @@
+use chrono::prelude::*;
+use rpiapptmon::outbox::{Backoff, Outbox};
+use rpiapptmon::sinks::influxdb;
+use rpiapptmon::sinks::*;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TS_MS : i64 = 1_600_000_000_123;
+
+fn sample(values : Value) -> Sample {
+    Sample {
+        ts     : Utc.timestamp_millis_opt(TS_MS).unwrap(),
+        values : values.as_object().unwrap().clone(),
+    }
+}
+
+struct Request {
+    url           : String,
+    body          : String,
+    content_type  : Option<String>,
+    authorization : Option<String>,
+}
+
+// Local stand-in HTTP server answering every request with `status`
+fn http_server(addr : &str, status : u16) -> mpsc::Receiver<Request> {
+    let server = tiny_http::Server::http(addr).unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let header = |name : &'static str| request.headers().iter()
+                .find(|h| h.field.equiv(name))
+                .map(|h| h.value.as_str().to_owned());
+            let (content_type, authorization) = (header("Content-Type"), header("Authorization"));
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let url = request.url().to_owned();
+            let _ = tx.send(Request { url, body, content_type, authorization });
+            let _ = request.respond(tiny_http::Response::empty(status));
+        }
+    });
+    rx
+}
+
+#[test]
+fn thingsboard_payload() {
+    let rx = http_server("127.0.0.1:18201", 200);
+    let mut tb = ThingsBoard::new("http://127.0.0.1:18201/api/v1/TOKEN/telemetry");
+    tb.send(&sample(json!({ "temperature" : 21.5, "humidity" : 40 }))).unwrap();
+    let req = rx.recv().unwrap();
+    assert_eq!(req.url, "/api/v1/TOKEN/telemetry");
+    assert_eq!(req.content_type.as_deref(), Some("application/json"));
+    let body : Value = serde_json::from_str(&req.body).unwrap();
+    assert_eq!(body, json!({ "ts" : TS_MS, "values" : { "temperature" : 21.5, "humidity" : 40 } }));
+}
+
+#[test]
+fn thingsboard_error_status() {
+    let _rx = http_server("127.0.0.1:18202", 500);
+    let mut tb = ThingsBoard::new("http://127.0.0.1:18202/api/v1/TOKEN/telemetry");
+    assert!(matches!(tb.send(&sample(json!({ "temperature" : 21.5 }))), Err(SinkError::Http(_))));
+}
+
+#[test]
+fn forwarder_replays_in_order() {
+    let dir = std::env::temp_dir().join(format!("rpiapptmon-test-sinks-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let conf = SinkConf::ThingsBoard { url : "http://127.0.0.1:18203/api/v1/TOKEN/telemetry".to_owned() };
+    let outbox = Outbox::open(dir.join("thingsboard.jsonl"), 10).unwrap();
+    let mut fwd = Forwarder::new("thingsboard", open(&conf), outbox, Backoff::new(Duration::from_millis(10), Duration::from_millis(100)));
+
+    // Server down : the sample stays queued and the next attempt is delayed
+    fwd.push(&sample(json!({ "temperature" : 1.0 }))).unwrap();
+    let flush = fwd.flush(Instant::now()).unwrap();
+    assert_eq!(flush.sent, 0);
+    assert!(flush.error.is_some());
+    fwd.push(&sample(json!({ "temperature" : 2.0 }))).unwrap();
+    assert_eq!(fwd.flush(Instant::now()).unwrap().sent, 0);
+    assert_eq!(fwd.queued(), 2);
+
+    let rx = http_server("127.0.0.1:18203", 200);
+    thread::sleep(Duration::from_millis(20));
+    let flush = fwd.flush(Instant::now()).unwrap();
+    assert_eq!(flush.sent, 2);
+    assert!(flush.error.is_none());
+    assert_eq!(fwd.queued(), 0);
+    for expected in [1.0, 2.0].iter() {
+        let body : Value = serde_json::from_str(&rx.recv().unwrap().body).unwrap();
+        assert_eq!(body["values"]["temperature"], json!(expected));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn influxdb_line_protocol() {
+    let s = sample(json!({
+        "temperature" : 21.5,
+        "a b=c,d"     : 1,
+        "light_on"    : true,
+        "forecast"    : "say \"rain\" \\ soon",
+        "ignored"     : null,
+    }));
+    assert_eq!(
+        influxdb::line("rpi app,1", &s).unwrap(),
+        format!("rpi\\ app\\,1 a\\ b\\=c\\,d=1,forecast=\"say \\\"rain\\\" \\\\ soon\",light_on=true,temperature=21.5 {}", TS_MS),
+    );
+    assert_eq!(influxdb::line("rpi", &sample(json!({ "ignored" : null }))), None);
+}
+
+#[test]
+fn influxdb_write() {
+    let rx = http_server("127.0.0.1:18204", 204);
+    let mut influx = InfluxDb::new(InfluxDbConf {
+        url         : "http://127.0.0.1:18204/".to_owned(),
+        database    : "home".to_owned(),
+        measurement : "rpiapptmon".to_owned(),
+        token       : Some("user:pass".to_owned()),
+    });
+    influx.send(&sample(json!({ "temperature" : 21.5 }))).unwrap();
+    let req = rx.recv().unwrap();
+    assert_eq!(req.url, "/write?db=home&precision=ms");
+    assert_eq!(req.authorization.as_deref(), Some("Token user:pass"));
+    assert_eq!(req.body, format!("rpiapptmon temperature=21.5 {}", TS_MS));
+}
+
+#[test]
+fn json_lines() {
+    let path = std::env::temp_dir().join(format!("rpiapptmon-test-sinks-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut jsonl = JsonLines::new(Some(path.clone()));
+    jsonl.send(&sample(json!({ "temperature" : 1.0 }))).unwrap();
+    jsonl.send(&sample(json!({ "temperature" : 2.0 }))).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines : Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(Sample::from_json(lines[1]), Some(sample(json!({ "temperature" : 2.0 }))));
+    // An unusable line is skipped by the forwarder rather than replayed
+    assert_eq!(Sample::from_json("{\"ts\":-99999999999999999,\"values\":{}}"), None);
+    assert_eq!(Sample::from_json("{\"ts\":-1,\"values\":{}}").unwrap().ts.timestamp_millis(), -1);
+    let _ = std::fs::remove_file(&path);
+}
+
+fn read_packet(stream : &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    let mut kind = [0u8; 1];
+    stream.read_exact(&mut kind).ok()?;
+    let (mut len, mut shift) = (0usize, 0);
+    loop {
+        let mut b = [0u8; 1];
+        stream.read_exact(&mut b).ok()?;
+        len |= ((b[0] & 0x7F) as usize) << shift;
+        shift += 7;
+        if b[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).ok()?;
+    Some((kind[0], body))
+}
+
+fn take_str(body : &[u8]) -> (String, &[u8]) {
+    let len = u16::from_be_bytes([body[0], body[1]]) as usize;
+    (String::from_utf8(body[2..2 + len].to_vec()).unwrap(), &body[2 + len..])
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Connect { client_id : String, flags : u8, keep_alive : u16, username : Option<String> },
+    Publish { kind : u8, topic : String, id : u16, payload : String },
+    Disconnect,
+    Closed,
+}
+
+// Local stand-in broker. Each connection answers CONNECT with `connack` and
+// a PUBLISH with the raw packets of `puback(id)`, or is closed on `None`.
+fn broker<F>(addr : &str, connack : u8, puback : F) -> mpsc::Receiver<Event>
+    where F : Fn(u16) -> Option<Vec<Vec<u8>>> + Send + Sync + 'static
+{
+    let listener = TcpListener::bind(addr).unwrap();
+    let puback = std::sync::Arc::new(puback);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let (mut stream, tx, puback) = (stream.unwrap(), tx.clone(), puback.clone());
+            thread::spawn(move || {
+                while let Some((kind, body)) = read_packet(&mut stream) {
+                    match kind >> 4 {
+                        1 => {
+                            let (protocol, rest) = take_str(&body);
+                            assert_eq!((protocol.as_str(), rest[0]), ("MQTT", 4));
+                            let (flags, keep_alive) = (rest[1], u16::from_be_bytes([rest[2], rest[3]]));
+                            let (client_id, rest) = take_str(&rest[4..]);
+                            let username = if flags & 0x80 != 0 { Some(take_str(rest).0) } else { None };
+                            tx.send(Event::Connect { client_id, flags, keep_alive, username }).unwrap();
+                            stream.write_all(&[0x20, 2, 0, connack]).unwrap();
+                        },
+                        3 => {
+                            let (topic, rest) = take_str(&body);
+                            let id = u16::from_be_bytes([rest[0], rest[1]]);
+                            let payload = String::from_utf8(rest[2..].to_vec()).unwrap();
+                            tx.send(Event::Publish { kind, topic, id, payload }).unwrap();
+                            match puback(id) {
+                                Some(packets) => for packet in packets {
+                                    stream.write_all(&packet).unwrap();
+                                },
+                                None => break,
+                            }
+                        },
+                        14 => tx.send(Event::Disconnect).unwrap(),
+                        _ => (),
+                    }
+                }
+                let _ = tx.send(Event::Closed);
+            });
+        }
+    });
+    rx
+}
+
+fn puback(id : u16) -> Vec<u8> {
+    let id = id.to_be_bytes();
+    vec![0x40, 2, id[0], id[1]]
+}
+
+fn mqtt_conf(host : &str, mode : MqttMode) -> MqttConf {
+    MqttConf {
+        host       : host.to_owned(),
+        client_id  : "rpiapptmon".to_owned(),
+        username   : Some("TOKEN".to_owned()),
+        password   : None,
+        keep_alive : Duration::from_secs(60),
+        mode,
+    }
+}
+
+#[test]
+fn mqtt_thingsboard() {
+    let rx = broker("127.0.0.1:18205", 0, |id| Some(vec![puback(id)]));
+    let mut mqtt = Mqtt::new(mqtt_conf("127.0.0.1:18205", MqttMode::ThingsBoard));
+    mqtt.send(&sample(json!({ "temperature" : 1.0 }))).unwrap();
+    mqtt.send(&sample(json!({ "temperature" : 2.0 }))).unwrap();
+    drop(mqtt);
+
+    assert_eq!(rx.recv().unwrap(), Event::Connect {
+        client_id  : "rpiapptmon".to_owned(),
+        flags      : 0x82,
+        keep_alive : 60,
+        username   : Some("TOKEN".to_owned()),
+    });
+    // One connection, QoS 1 with increasing packet ids
+    for (id, value) in [(1, 1.0), (2, 2.0)].iter() {
+        match rx.recv().unwrap() {
+            Event::Publish { kind, topic, id : got, payload } => {
+                assert_eq!((kind, topic.as_str(), got), (0x32, "v1/devices/me/telemetry", *id));
+                let payload : Value = serde_json::from_str(&payload).unwrap();
+                assert_eq!(payload, json!({ "ts" : TS_MS, "values" : { "temperature" : value } }));
+            },
+            e => panic!("{:?}", e),
+        }
+    }
+    assert_eq!(rx.recv().unwrap(), Event::Disconnect);
+}
+
+#[test]
+fn mqtt_plain_topics() {
+    let rx = broker("127.0.0.1:18206", 0, |id| Some(vec![puback(id)]));
+    let mut conf = mqtt_conf("127.0.0.1:18206", MqttMode::Plain { topic : "home/rpi".to_owned() });
+    conf.username = None;
+    let mut mqtt = Mqtt::new(conf);
+    mqtt.send(&sample(json!({ "temperature" : 21.5, "forecast" : "fine" }))).unwrap();
+
+    assert!(matches!(rx.recv().unwrap(), Event::Connect { flags : 0x02, username : None, .. }));
+    let published : Vec<(String, String)> = (0..2).map(|_| match rx.recv().unwrap() {
+        Event::Publish { topic, payload, .. } => (topic, payload),
+        e => panic!("{:?}", e),
+    }).collect();
+    assert_eq!(published, vec![
+        ("home/rpi/forecast".to_owned(), "fine".to_owned()),
+        ("home/rpi/temperature".to_owned(), "21.5".to_owned()),
+    ]);
+}
+
+#[test]
+fn mqtt_plain_resumes() {
+    // The broker drops the connection instead of acknowledging the second key
+    let rx = broker("127.0.0.1:18210", 0, |id| if id == 2 { None } else { Some(vec![puback(id)]) });
+    let mut mqtt = Mqtt::new(mqtt_conf("127.0.0.1:18210", MqttMode::Plain { topic : "rpi".to_owned() }));
+    let s = sample(json!({ "a" : 1, "b" : 2, "c" : 3 }));
+    assert!(mqtt.send(&s).is_err());
+    mqtt.send(&s).unwrap();
+    drop(mqtt);
+
+    // The acknowledged key is not published again
+    let topics : Vec<String> = rx.iter()
+        .take_while(|e| *e != Event::Disconnect)
+        .filter_map(|e| match e {
+            Event::Publish { topic, .. } => Some(topic),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(topics, ["rpi/a", "rpi/b", "rpi/b", "rpi/c"]);
+}
+
+#[test]
+fn mqtt_puback() {
+    // A PUBACK for another packet is skipped, the one for the PUBLISH is waited for
+    let rx = broker("127.0.0.1:18207", 0, |id| Some(vec![puback(id + 100), puback(id)]));
+    let mut mqtt = Mqtt::new(mqtt_conf("127.0.0.1:18207", MqttMode::ThingsBoard));
+    mqtt.send(&sample(json!({ "temperature" : 1.0 }))).unwrap();
+    mqtt.send(&sample(json!({ "temperature" : 2.0 }))).unwrap();
+    drop(mqtt);
+    let publishes = rx.iter().take_while(|e| *e != Event::Disconnect)
+        .filter(|e| matches!(e, Event::Publish { .. }))
+        .count();
+    assert_eq!(publishes, 2);
+
+    // No PUBACK before the broker drops the connection : the sample fails
+    // and the next one is sent over a new connection
+    let rx = broker("127.0.0.1:18208", 0, |id| if id == 1 { None } else { Some(vec![puback(id)]) });
+    let mut mqtt = Mqtt::new(mqtt_conf("127.0.0.1:18208", MqttMode::ThingsBoard));
+    assert!(mqtt.send(&sample(json!({ "temperature" : 1.0 }))).is_err());
+    mqtt.send(&sample(json!({ "temperature" : 2.0 }))).unwrap();
+    let connects = rx.iter().take(5).filter(|e| matches!(e, Event::Connect { .. })).count();
+    assert_eq!(connects, 2);
+}
+
+#[test]
+fn mqtt_refused() {
+    let _rx = broker("127.0.0.1:18209", 5, |id| Some(vec![puback(id)]));
+    let mut mqtt = Mqtt::new(mqtt_conf("127.0.0.1:18209", MqttMode::ThingsBoard));
+    match mqtt.send(&sample(json!({ "temperature" : 1.0 }))) {
+        Err(SinkError::Protocol(msg)) => assert!(msg.contains("return code 5"), "{}", msg),
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::prelude::*;
+use rpiapptmon::storage::*;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const KEEP_ALL : Retention = Retention { raw : None, minute : None, hour : None, day : None };
+
+fn store_dir(name : &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rpiapptmon-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// Start of a day
+fn t0() -> DateTime<Utc> {
+    Utc.timestamp_opt(1_600_000_000 - 1_600_000_000 % 86400, 0).unwrap()
+}
+
+fn at(s : i64) -> DateTime<Utc> {
+    t0() + chrono::Duration::seconds(s)
+}
+
+#[test]
+fn roll_ups() {
+    let dir = store_dir("roll-ups");
+    let retention = Retention { raw : Some(Duration::from_secs(3 * 3600)), ..KEEP_ALL };
+    {
+        let mut store = Store::open(&dir, retention).unwrap();
+        for i in 0..240 {
+            store.append("temperature", at(i * 30), i as f64).unwrap();
+        }
+    }
+    let mut store = Store::open(&dir, retention).unwrap();
+    let end = at(2 * 86400);
+    let minutes = store.query("temperature", Resolution::Minute, t0(), end).unwrap();
+    assert_eq!(minutes.len(), 120);
+    assert_eq!(minutes[0], Point { ts : t0().timestamp(), count : 2, min : 0.0, avg : 0.5, max : 1.0 });
+    let hours = store.query("temperature", Resolution::Hour, t0(), end).unwrap();
+    assert_eq!(hours.len(), 2);
+    assert_eq!((hours[0].count, hours[0].avg, hours[1].count), (120, 59.5, 120));
+    let days = store.query("temperature", Resolution::Day, t0(), end).unwrap();
+    assert_eq!(days.len(), 1);
+    assert_eq!(days[0].count, 240);
+
+    // The next day closes the first one and drops the old raw samples
+    store.append("temperature", at(25 * 3600), 1000.0).unwrap();
+    assert_eq!(store.query("temperature", Resolution::Raw, t0(), end).unwrap().len(), 1);
+    let days = store.query("temperature", Resolution::Day, t0(), end).unwrap();
+    assert_eq!(days.len(), 2);
+    assert_eq!((days[0].count, days[0].max), (240, 239.0));
+    drop(store);
+
+    let store = Store::open(&dir, retention).unwrap();
+    assert_eq!(store.query("temperature", Resolution::Day, t0(), end).unwrap().len(), 2);
+    assert_eq!(std::fs::metadata(dir.join("1d.dat")).unwrap().len(), 38);
+    assert_eq!(std::fs::metadata(dir.join("1h.dat")).unwrap().len(), 38 * 2);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn time_window() {
+    let dir = store_dir("time-window");
+    let mut store = Store::open(&dir, KEEP_ALL).unwrap();
+    // Several index steps of two interleaved series, slightly out of order
+    for i in 0..3000 {
+        store.append("pressure", at(i * 10 + 5), i as f64).unwrap();
+        store.append("temperature", at(i * 10), i as f64).unwrap();
+    }
+    let check = |store : &Store| {
+        let points = store.query("temperature", Resolution::Raw, at(12_000), at(12_100)).unwrap();
+        let values : Vec<f64> = points.iter().map(|p| p.avg).collect();
+        assert_eq!(values, (1200..1210).map(|i| i as f64).collect::<Vec<_>>());
+        assert_eq!(store.query("temperature", Resolution::Raw, t0(), at(30_000)).unwrap().len(), 3000);
+        assert_eq!(store.query("pressure", Resolution::Raw, at(29_990), at(40_000)).unwrap().len(), 1);
+        assert!(store.query("humidity", Resolution::Raw, t0(), at(30_000)).unwrap().is_empty());
+    };
+    check(&store);
+    drop(store);
+    check(&Store::open(&dir, KEEP_ALL).unwrap());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn selection_outlives_the_lock() {
+    let dir = store_dir("selection");
+    let retention = Retention { raw : Some(Duration::from_secs(3600)), ..KEEP_ALL };
+    let mut store = Store::open(&dir, retention).unwrap();
+    for i in 0..10 {
+        store.append("temperature", at(i * 60), i as f64).unwrap();
+    }
+    let selection = store.select("temperature", Resolution::Raw, t0(), at(86400)).unwrap();
+    // Samples written and files rewritten after the selection are not seen
+    store.append("temperature", at(7200), 10.0).unwrap();
+    store.enforce_retention(at(7200)).unwrap();
+    assert_eq!(selection.read().unwrap().len(), 10);
+    assert_eq!(store.query("temperature", Resolution::Raw, t0(), at(86400)).unwrap().len(), 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn retention_cut() {
+    let dir = store_dir("retention-cut");
+    let retention = Retention { raw : Some(Duration::from_secs(10_000)), ..KEEP_ALL };
+    let mut store = Store::open(&dir, retention).unwrap();
+    // Several index steps, slightly out of order
+    for i in 0..3000 {
+        store.append("pressure", at(i * 10 + 5), i as f64).unwrap();
+        store.append("temperature", at(i * 10), i as f64).unwrap();
+    }
+    store.enforce_retention(at(30_000)).unwrap();
+    let points = store.query("temperature", Resolution::Raw, t0(), at(40_000)).unwrap();
+    assert_eq!((points.len(), points[0].ts), (1000, at(20_000).timestamp()));
+    assert_eq!(std::fs::metadata(dir.join("raw.dat")).unwrap().len(), 2000 * 18);
+    // Nothing older left, nothing rewritten
+    let modified = std::fs::metadata(dir.join("raw.dat")).unwrap().modified().unwrap();
+    store.enforce_retention(at(30_000)).unwrap();
+    assert_eq!(std::fs::metadata(dir.join("raw.dat")).unwrap().modified().unwrap(), modified);
+    drop(store);
+
+    let store = Store::open(&dir, retention).unwrap();
+    assert_eq!(store.query("pressure", Resolution::Raw, at(29_000), at(40_000)).unwrap().len(), 100);
+    assert_eq!(store.query("temperature", Resolution::Minute, t0(), at(40_000)).unwrap().len(), 500);
+    let _ = std::fs::remove_dir_all(&dir);
+}
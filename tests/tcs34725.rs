@@ -0,0 +1,164 @@
+use rpiapptmon::sensors::light::*;
+use rpiapptmon::sensors::mock::MockI2CDevice;
+use rpiapptmon::sensors::{Quantity, Sensor, Unit};
+use std::sync::{Arc, Mutex};
+
+fn device() -> MockI2CDevice {
+    let dev = MockI2CDevice::with_reg_mask(0x1F);
+    dev.set_reg(0x12, 0x44);
+    // RGBC valid
+    dev.set_reg(0x13, 0x01);
+    dev
+}
+
+#[test]
+fn byte_order_and_lux() {
+    let dev = device();
+    // Little endian C = 0x0302, R = 0x0100, G = 0x0180, B = 0x0100
+    dev.set_regs(0x14, &[0x02, 0x03, 0x00, 0x01, 0x80, 0x01, 0x00, 0x01]);
+    let mut light = Light::new(dev, LightConf::default().integration_ms(24.0)).unwrap();
+    assert_eq!(light.get_all().unwrap(), (0x302, 0x100, 0x180, 0x100));
+    assert_eq!(light.get(Color::Green).unwrap(), 0x180);
+
+    // IR = (256 + 384 + 256 - 770) / 2 = 63, at 24 ms and 16x : 1.24 counts per lux
+    let (lx, k) = light.get_lux_cct().unwrap();
+    let g2 = 0.136 * 193.0 + 321.0 - 0.444 * 193.0;
+    assert!((lx - g2 / (24.0 * 16.0 / 310.0)).abs() < 1e-3, "{}", lx);
+    assert!((k.unwrap() - (3810.0 + 1391.0)).abs() < 1e-3);
+
+    let ms = light.measure().unwrap();
+    let quantities : Vec<_> = ms.iter().map(|m| m.quantity).collect();
+    assert_eq!(quantities, LIGHT_QUANTITIES[..9]);
+    assert_eq!((ms[4].unit, ms[5].unit), (Unit::Lux, Unit::Kelvin));
+}
+
+#[test]
+fn no_red_light() {
+    assert_eq!(cct(100, 0, 50, 50), None);
+    assert_eq!(lux(100, 0, 0, 0, 0xF6, Gain::X1), 0.0);
+}
+
+// Starting a cycle fills the channels with `level` counts per gain and
+// integration cycle, up to the chip's full scale
+fn lit_by(dev : &MockI2CDevice, level : Arc<Mutex<f32>>) {
+    dev.on_write(0x00, move |r| {
+        if r[0x00] & 0x02 != 0 {
+            let gain = [1.0, 4.0, 16.0, 60.0][r[0x0F] as usize & 3];
+            let cycles = 256.0 - r[0x01] as f32;
+            let clear = (*level.lock().unwrap() * gain * cycles).min((cycles * 1024.0).min(65535.0)) as u16;
+            r[0x14..0x16].copy_from_slice(&clear.to_le_bytes());
+            for reg in &[0x16, 0x18, 0x1A] {
+                r[*reg..*reg + 2].copy_from_slice(&(clear / 3).to_le_bytes());
+            }
+            r[0x13] = 0x01;
+        }
+    });
+}
+
+#[test]
+fn auto_range() {
+    let dev = device();
+    let level = Arc::new(Mutex::new(1.0));
+    lit_by(&dev, level.clone());
+    let mut light = Light::new(dev, LightConf::default().integration_ms(153.6)).unwrap();
+    light.set_auto_range(true).unwrap();
+    assert_eq!(light.gain().factor(), 16.0);
+
+    // Dark : most sensitive range
+    let rd = light.read().unwrap();
+    assert_eq!((rd.gain.factor(), rd.integration_ms()), (60.0, 614.4));
+    assert!(!rd.saturated);
+
+    // Sunlight : steps down until the reading fits
+    *level.lock().unwrap() = 500.0;
+    let rd = light.read().unwrap();
+    assert!(!rd.saturated && rd.clear < 65535);
+
+    // Brighter than the least sensitive range
+    *level.lock().unwrap() = 1e6;
+    let rd = light.read().unwrap();
+    assert!(rd.saturated);
+    assert_eq!((rd.gain.factor(), rd.integration_ms()), (1.0, 9.6));
+
+    // Settles and stays at moderate light
+    *level.lock().unwrap() = 10.0;
+    light.read().unwrap();
+    let (a, b) = (light.read().unwrap(), light.read().unwrap());
+    assert_eq!((a.gain.factor(), a.atime), (b.gain.factor(), b.atime));
+    assert!((a.lux() - b.lux()).abs() < 1e-3);
+
+    // A fixed range stops auto-ranging
+    light.set_range(Gain::X1, 2.4).unwrap();
+    let rd = light.read().unwrap();
+    assert_eq!((rd.gain.factor(), rd.atime), (1.0, 0xFF));
+}
+
+#[test]
+fn saturation_levels() {
+    assert_eq!(saturation(0xFF), 1024 * 3 / 4);
+    assert_eq!(saturation(0xC0), 65535);
+    assert_eq!(saturation(0x00), 65535);
+}
+
+#[test]
+fn watch_switches() {
+    let dev = device();
+    dev.set_regs(0x14, &[10, 0, 3, 0, 3, 0, 3, 0]);
+    let mut light = Light::new(dev.clone(), LightConf::default().integration_ms(24.0)).unwrap();
+    light.watch(Watch { off : 50, on : 200, pers : Persistence::Cycles5 }).unwrap();
+    assert_eq!(light.lights_on(), Some(false));
+    // AIEN | AEN | PON, 5 cycles, armed for the lights switching on
+    assert_eq!((dev.reg(0x00), dev.reg(0x0C)), (0x13, 0x04));
+    assert_eq!([dev.reg(0x04), dev.reg(0x05), dev.reg(0x06), dev.reg(0x07)], [0, 0, 200, 0]);
+    assert_eq!(light.poll_event().unwrap(), None);
+
+    // Only the clear channel interrupt special function drops AINT
+    dev.on_command(0xE6, |regs| regs[0x13] &= !0x10);
+    dev.set_reg(0x13, 0x11);
+    assert_eq!(light.poll_event().unwrap(), Some(LightEvent::On));
+    assert_eq!(dev.reg(0x13), 0x01);
+    assert_eq!(light.poll_event().unwrap(), None);
+    // Armed for the lights switching off
+    assert_eq!([dev.reg(0x04), dev.reg(0x05), dev.reg(0x06), dev.reg(0x07)], [50, 0, 255, 255]);
+
+    // The state is reported with every reading, the switch only with the
+    // reading that saw it
+    let ms = light.measure().unwrap();
+    assert_eq!(ms.last().map(|m| (m.quantity, m.value)), Some((Quantity::LightOn, 1.0)));
+    dev.set_reg(0x13, 0x11);
+    let ms = light.measure().unwrap();
+    let tail : Vec<_> = ms[ms.len() - 2..].iter().map(|m| (m.quantity, m.value)).collect();
+    assert_eq!(tail, [(Quantity::LightOn, 0.0), (Quantity::LightSwitch, -1.0)]);
+    let ms = light.measure().unwrap();
+    assert_eq!(ms.last().map(|m| (m.quantity, m.value)), Some((Quantity::LightOn, 0.0)));
+    assert_eq!(light.poll_event().unwrap(), None);
+    assert_eq!(dev.reg(0x00), 0x13);
+
+    light.unwatch().unwrap();
+    assert_eq!(dev.reg(0x00), 0x01);
+    assert_eq!(light.lights_on(), None);
+}
+
+#[test]
+fn low_power_mode() {
+    let dev = device();
+    dev.set_regs(0x14, &[10, 0, 3, 0, 3, 0, 3, 0]);
+    let mut light = Light::new(dev.clone(), LightConf::default().mode(LightMode::LowPower(WaitTime::from_ms(1000.0)))).unwrap();
+    // WEN | AEN | PON, WLONG, 1000 / 28.8 ms rounded to 35 cycles
+    assert_eq!((dev.reg(0x00), dev.reg(0x0D), dev.reg(0x03)), (0x0B, 0x02, 221));
+    assert_eq!(WaitTime::from_ms(1000.0).duration().as_micros(), 35 * 28800);
+    assert_eq!(WaitTime::from_ms(100.0).duration().as_micros(), 42 * 2400);
+    // Reading leaves it running
+    light.get_all().unwrap();
+    assert_eq!(dev.reg(0x00), 0x0B);
+
+    light.set_mode(LightMode::OneShot).unwrap();
+    assert_eq!((dev.reg(0x00), dev.reg(0x0D)), (0x01, 0x00));
+    light.set_mode(LightMode::Continuous).unwrap();
+    assert_eq!(dev.reg(0x00), 0x03);
+    // Unwatching goes back to the configured mode
+    light.watch(Watch { off : 5, on : 50, pers : Persistence::Every }).unwrap();
+    assert_eq!(dev.reg(0x00), 0x13);
+    light.unwatch().unwrap();
+    assert_eq!(dev.reg(0x00), 0x03);
+}
@@ -0,0 +1,87 @@
+use chrono::prelude::*;
+use rpiapptmon::sampling::Snapshot;
+use rpiapptmon::sensors::{Measurement, Quantity, Unit};
+use rpiapptmon::storage::{Retention, Store};
+use rpiapptmon::web::{self, WebState};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const KEEP_ALL : Retention = Retention { raw : None, minute : None, hour : None, day : None };
+
+// Ten minutes of temperature up to now, and a latest snapshot
+fn state(name : &str) -> Arc<WebState> {
+    let dir = std::env::temp_dir().join(format!("rpiapptmon-test-web-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut store = Store::open(&dir, KEEP_ALL).unwrap();
+    let now = Utc::now().timestamp();
+    for i in 0..10 {
+        store.append("temperature", Utc.timestamp_opt(now - 600 + i * 60, 0).unwrap(), 20.0 + i as f64).unwrap();
+    }
+    let state = Arc::new(WebState::new(Some(Arc::new(Mutex::new(store)))));
+    state.set_latest(Arc::new(Snapshot::new(Utc::now(), vec![
+        Measurement::new("bmp180", Quantity::Temperature, Unit::DegC, 21.5),
+        Measurement::new("hts221", Quantity::Temperature, Unit::DegC, 22.0),
+    ])));
+    state
+}
+
+fn get(state : &WebState, url : &str) -> (u16, Value) {
+    let reply = web::handle(state, url);
+    (reply.status, serde_json::from_str(&reply.body).unwrap())
+}
+
+#[test]
+fn api() {
+    let state = state("api");
+    assert_eq!(get(&state, "/api/series"), (200, json!(["temperature"])));
+    let (status, current) = get(&state, "/api/current");
+    assert_eq!(status, 200);
+    assert_eq!(current["temperature"]["value"], json!(21.5));
+    assert_eq!(current["temperature_hts221"]["value"], json!(22.0));
+
+    let (status, history) = get(&state, "/api/history?key=temperature");
+    assert_eq!(status, 200);
+    assert_eq!(history["res"], "1m");
+    let (_, history) = get(&state, "/api/history?key=temperature&res=raw");
+    assert_eq!(history["points"].as_array().unwrap().len(), 10);
+    assert_eq!(history["points"][9]["avg"], json!(29.0));
+
+    assert_eq!(get(&state, "/api/history").0, 400);
+    assert_eq!(get(&state, "/api/history?key=temperature&from=10&to=5").0, 400);
+    assert_eq!(get(&state, "/api/history?key=temperature&res=2h").0, 400);
+    assert_eq!(get(&state, "/nope").0, 404);
+}
+
+#[test]
+fn out_of_range_time() {
+    let state = state("range");
+    assert_eq!(get(&state, "/api/history?key=x&from=-99999999999999&to=1").0, 400);
+    assert_eq!(get(&state, &format!("/api/history?key=x&to={}", i64::MIN)).0, 400);
+    assert_eq!(get(&state, "/api/series").0, 200);
+}
+
+#[test]
+fn poisoned_store() {
+    let state = state("poisoned");
+    let store = state.store.clone().unwrap();
+    let _ = thread::spawn(move || {
+        let _guard = store.lock().unwrap();
+        panic!("poisons the store");
+    }).join();
+    assert_eq!(get(&state, "/api/series"), (200, json!(["temperature"])));
+    assert_eq!(get(&state, "/api/history?key=temperature&res=raw").0, 200);
+}
+
+#[test]
+fn server() {
+    web::spawn("127.0.0.1:18123", state("server")).unwrap();
+    let mut stream = TcpStream::connect("127.0.0.1:18123").unwrap();
+    write!(stream, "GET / HTTP/1.0\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    assert!(reply.starts_with("HTTP/1.0 200") || reply.starts_with("HTTP/1.1 200"), "{}", reply);
+    assert!(reply.contains("text/html"));
+}